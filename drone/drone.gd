@@ -18,6 +18,14 @@ signal emit_log(message: String)
 	set(v):
 		position = Vector3(v) + Vector3(0.5, 0.5, 0.5)
 		coord = v
+@export var facing := 5:
+	set(v):
+		match v:
+			3: rotation.y = PI / 2
+			4: rotation.y = -PI / 2
+			5: rotation.y = PI
+			6: rotation.y = 0
+		facing = v
 
 var level: LevelController = null
 var module: WasmModule = null
@@ -37,9 +45,9 @@ func update_data(grid_data: PackedByteArray, drone_data: PackedByteArray):
 
 func step() -> PackedByteArray:
 	if inst == null:
-		return PackedByteArray([0, 0, 0])
+		return PackedByteArray([0, 0, 0, 0])
 	inst.call_wasm(&"step", [])
-	return inst.memory_read(ptr + 16, 3)
+	return inst.memory_read(ptr + 16, 4)
 
 func __log(p: int, n: int) -> void:
 	emit_log.emit(inst.memory_read(p, n).get_string_from_utf8())
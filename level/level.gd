@@ -7,8 +7,8 @@ class_name LevelController
 
 signal emit_log(message: String)
 
-const MESH_SIZE := 44
-const DRONE_SIZE := 52
+const MESH_SIZE := 56
+const DRONE_SIZE := 64
 
 @export var material: Material = null
 @export_range(1, 128) var size_x: int = 1
@@ -49,6 +49,7 @@ func step():
 			inst.get_32(p + 4),
 			inst.get_32(p + 8),
 		)
+		d.facing = inst.memory_read(p + 55, 1)[0]
 
 func mark_all_dirty():
 	if inst == null:
@@ -74,6 +75,11 @@ func pubsub_get(i: int):
 	inst.call_wasm(&"pubsub_pop", [i])
 	return [__key, __msg] if __written else null
 
+func pubsub_peek(i: int):
+	__written = false
+	inst.call_wasm(&"pubsub_peek", [i])
+	return [__key, __msg] if __written else null
+
 func update_meshes():
 	if inst == null:
 		return
@@ -108,6 +114,11 @@ func update_meshes():
 			vertext_cnt,
 			TYPE_PACKED_VECTOR2_ARRAY,
 		)
+		arr[Mesh.ARRAY_COLOR] = inst.get_array(
+			inst.get_32(p + 32),
+			vertext_cnt,
+			TYPE_PACKED_COLOR_ARRAY,
+		)
 		arr[Mesh.ARRAY_INDEX] = inst.get_array(
 			inst.get_32(p + 28),
 			inst.get_32(p + 8),
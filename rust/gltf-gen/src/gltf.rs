@@ -10,6 +10,8 @@ use serde::{Serialize, Serializer};
 #[serde(rename_all = "camelCase")]
 pub struct Gltf {
     pub asset: Asset,
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub extensions_used: Vec<String>,
     pub buffers: Vec<Buffer>,
     pub buffer_views: Vec<BufferView>,
     pub accessors: Vec<Accessor>,
@@ -324,6 +326,32 @@ pub struct Node {
     pub mesh: Option<usize>,
     #[serde(skip_serializing_if = "skip_if_none")]
     pub skin: Option<usize>,
+
+    #[serde(skip_serializing_if = "skip_if_none")]
+    pub extensions: Option<NodeExtensions>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NodeExtensions {
+    #[serde(rename = "EXT_mesh_gpu_instancing")]
+    pub mesh_gpu_instancing: MeshGpuInstancing,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeshGpuInstancing {
+    pub attributes: InstanceAttribute,
+}
+
+#[derive(Debug, Serialize, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub struct InstanceAttribute {
+    #[serde(skip_serializing_if = "skip_if_none")]
+    pub translation: Option<usize>,
+    #[serde(skip_serializing_if = "skip_if_none")]
+    pub rotation: Option<usize>,
+    #[serde(skip_serializing_if = "skip_if_none")]
+    pub scale: Option<usize>,
 }
 
 #[derive(Debug, Serialize)]
@@ -400,6 +428,8 @@ pub struct PBRMetallicRoughness {
 pub struct NormalTexture {
     pub index: usize,
     pub scale: f32,
+    #[serde(skip_serializing_if = "skip_if_none")]
+    pub extensions: Option<TextureInfoExtensions>,
 }
 
 #[derive(Debug, Serialize)]
@@ -413,6 +443,20 @@ pub struct OcclusionTexture {
 #[serde(rename_all = "camelCase")]
 pub struct TextureInfo {
     pub index: usize,
+    #[serde(skip_serializing_if = "skip_if_none")]
+    pub extensions: Option<TextureInfoExtensions>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TextureInfoExtensions {
+    #[serde(rename = "KHR_texture_transform")]
+    pub texture_transform: TextureTransform,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextureTransform {
+    pub scale: [f32; 2],
 }
 
 #[derive(Debug, Serialize)]
@@ -12,16 +12,17 @@ pub struct Index<'a> {
     pub named_mesh: HashMap<&'a str, gltf::MeshPrimitive>,
     pub named_material: HashMap<&'a str, usize>,
     pub named_skin: HashMap<&'a str, usize>,
-    pub image_file: HashMap<&'a str, usize>,
+    /// Image index and UV scale of padded images.
+    pub image_file: HashMap<&'a str, (usize, [f32; 2])>,
 
     pub sampler: HashMap<gltf::Sampler, usize>,
     pub texture: HashMap<gltf::Texture, usize>,
 }
 
 impl<'a> Index<'a> {
-    pub fn maybe_add_image<F, E>(&mut self, filename: &'a str, f: F) -> Result<usize, E>
+    pub fn maybe_add_image<F, E>(&mut self, filename: &'a str, f: F) -> Result<(usize, [f32; 2]), E>
     where
-        F: FnOnce(&str) -> Result<usize, E>,
+        F: FnOnce(&str) -> Result<(usize, [f32; 2]), E>,
     {
         Ok(match self.image_file.entry(filename) {
             Entry::Occupied(v) => *v.get(),
@@ -8,6 +8,8 @@ mod indexes;
 mod meshgen;
 mod othergen;
 mod parse;
+#[cfg(test)]
+mod tests;
 
 use std::fs::{read_to_string, File, OpenOptions};
 use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
@@ -34,6 +36,21 @@ struct Cli {
     /// Copyright of the asset
     #[arg(long)]
     copyright: Option<PathBuf>,
+
+    /// Require power-of-two texture sizes
+    #[arg(long)]
+    pot: bool,
+
+    /// Pad textures to power-of-two sizes (implies --pot)
+    ///
+    /// UVs are scaled back with KHR_texture_transform, so repeating
+    /// textures also repeat the padding.
+    #[arg(long)]
+    pot_pad: bool,
+
+    /// Reverse triangle winding of all meshes
+    #[arg(long)]
+    reverse_winding: bool,
 }
 
 fn main() -> Result<(), Error> {
@@ -69,6 +86,14 @@ fn process_file(input: PathBuf, output: PathBuf, cli: &Cli) -> Result<(), Error>
     let mut data: parse::Data =
         serde_json::from_reader(BufReader::with_capacity(4096, File::open(&input)?))?;
     data.filepath = input;
+    data.pot = if cli.pot_pad {
+        parse::PowerOfTwo::Pad
+    } else if cli.pot {
+        parse::PowerOfTwo::Error
+    } else {
+        parse::PowerOfTwo::Any
+    };
+    data.reverse_winding = cli.reverse_winding;
     for (name, i) in &mut data.animations {
         for (i, v) in i.keyframe.iter().enumerate() {
             if !v.time.is_finite() {
@@ -3,6 +3,7 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::iter;
 
 use anyhow::{bail, Error};
@@ -396,9 +397,118 @@ where
     buffer.resize((buffer.len() + 3) & !3, 0);
 }
 
+/// Split vertices per triangle, giving each the triangle's normal.
+///
+/// Vertex data is read from interleaved buffer past offset, and rewritten
+/// in triangle order.
+fn flat_shade(
+    buffer: &mut Vec<u8>,
+    offset: usize,
+    stride: usize,
+    normal_offset: usize,
+    indices: &mut Vec<usize>,
+    count: &mut usize,
+    indexed: bool,
+) {
+    let read = |data: &[u8], i: usize| {
+        let v = &data[i * stride..];
+        Vector3::from_fn(|j, _| f32::from_le_bytes(v[j * 4..j * 4 + 4].try_into().unwrap()))
+    };
+    let data = buffer.split_off(offset);
+    if !indexed {
+        indices.extend(0..*count);
+    }
+
+    for t in indices.chunks_exact(3) {
+        let (a, b, c) = (read(&data, t[0]), read(&data, t[1]), read(&data, t[2]));
+        let n = (b - a).cross(&(c - a)).try_normalize(f32::EPSILON);
+        for &i in t {
+            let start = buffer.len() + normal_offset;
+            buffer.extend_from_slice(&data[i * stride..(i + 1) * stride]);
+            if let Some(n) = &n {
+                for (d, s) in buffer[start..start + 12].iter_mut().zip(to_bytes(n)) {
+                    *d = s;
+                }
+            }
+        }
+    }
+
+    *count = indices.len() / 3 * 3;
+    indices.clear();
+    if indexed {
+        indices.extend(0..*count);
+    }
+}
+
+/// Flip winding of every triangle.
+///
+/// Indexed meshes swap indices, otherwise vertices are swapped in place.
+fn reverse_winding(data: &mut [u8], stride: usize, indices: &mut [usize], indexed: bool) {
+    if indexed {
+        for t in indices.chunks_exact_mut(3) {
+            t.swap(1, 2);
+        }
+    } else {
+        for t in data.chunks_exact_mut(stride * 3) {
+            let (a, b) = t[stride..].split_at_mut(stride);
+            a.swap_with_slice(b);
+        }
+    }
+}
+
+/// Recompute normals of vertices around shifted positions.
+///
+/// Vertex data is read from interleaved buffer. Returns normal deltas,
+/// akin to a morph target.
+fn recompute_normals(
+    data: &[u8],
+    stride: usize,
+    normal_offset: usize,
+    triangles: &[usize],
+    shift: &[(usize, Vector3<f32>)],
+) -> Vec<(usize, Vector3<f32>)> {
+    let read = |i: usize, offset: usize| {
+        let v = &data[i * stride + offset..];
+        Vector3::from_fn(|j, _| f32::from_le_bytes(v[j * 4..j * 4 + 4].try_into().unwrap()))
+    };
+    let shift: HashMap<usize, Vector3<f32>> = shift.iter().copied().collect();
+    let pos = |i| read(i, 0) + shift.get(&i).copied().unwrap_or_default();
+
+    // Vertices of triangles touching a shifted vertex
+    let mut affected = HashMap::<usize, Vector3<f32>>::new();
+    for t in triangles.chunks_exact(3) {
+        if t.iter().any(|i| shift.contains_key(i)) {
+            affected.extend(t.iter().map(|&i| (i, Vector3::zeros())));
+        }
+    }
+    for t in triangles.chunks_exact(3) {
+        if !t.iter().any(|i| affected.contains_key(i)) {
+            continue;
+        }
+        let (a, b, c) = (pos(t[0]), pos(t[1]), pos(t[2]));
+        let n = (b - a).cross(&(c - a));
+        for i in t {
+            if let Some(v) = affected.get_mut(i) {
+                *v += n;
+            }
+        }
+    }
+
+    let mut ret = affected
+        .into_iter()
+        .filter_map(|(i, n)| {
+            let d = n.try_normalize(f32::EPSILON)? - read(i, normal_offset);
+            (d.norm_squared() > 1e-12).then_some((i, d))
+        })
+        .collect::<Vec<_>>();
+    ret.sort_by_key(|&(i, _)| i);
+    ret
+}
+
 pub fn generate_mesh(
     mesh_name: &str,
     mesh: &parse::Mesh,
+    reverse: bool,
     gltf: &mut gltf::Gltf,
     buffer: &mut Vec<u8>,
 ) -> Result<gltf::MeshPrimitive, Error> {
@@ -445,11 +555,15 @@ pub fn generate_mesh(
             },
         }
     });
+    flags.normal |= mesh.flat_shading;
     flags.tangent &= flags.normal;
     flags.weights &= flags.joints;
 
     let mut data_index = Vec::new();
     let has_blend = !mesh.blend.is_empty();
+    if mesh.flat_shading && has_blend {
+        bail!("Error at mesh {mesh_name}: flat shading can't be used with blend");
+    }
 
     const POSITION_SIZE: usize = 4 * 3;
     let normal_offset = POSITION_SIZE;
@@ -1195,7 +1309,26 @@ pub fn generate_mesh(
         }
     }
 
+    if mesh.flat_shading {
+        flat_shade(
+            buffer,
+            view.byte_offset,
+            total_size,
+            normal_offset,
+            &mut indices,
+            &mut count,
+            flags.index,
+        );
+    }
     view.byte_length = count * total_size;
+    if reverse {
+        reverse_winding(
+            &mut buffer[view.byte_offset..view.byte_offset + view.byte_length],
+            total_size,
+            &mut indices,
+            flags.index,
+        );
+    }
 
     let gltf::Gltf {
         buffer_views,
@@ -1312,6 +1445,7 @@ pub fn generate_mesh(
         });
     }
 
+    let vertex_data = view.byte_offset..view.byte_offset + view.byte_length;
     buffer_views.push(view);
     if flags.index {
         buffer_views.push(gltf::BufferView {
@@ -1320,7 +1454,10 @@ pub fn generate_mesh(
             byte_length: indices.len() * 4,
             byte_stride: 0,
         });
-        buffer.extend(indices.into_iter().flat_map(|v| (v as u32).to_le_bytes()));
+        buffer.extend(indices.iter().flat_map(|&v| (v as u32).to_le_bytes()));
+    }
+    if !flags.index {
+        indices.extend(0..count);
     }
 
     // Blends
@@ -1329,6 +1466,7 @@ pub fn generate_mesh(
     let mut tangent = Vec::new();
     let mut uv = Vec::new();
     for a in &mesh.blend {
+        let mut recompute = false;
         for i in a {
             #[allow(irrefutable_let_patterns)]
             if let parse::BlendData::ShiftVertex {
@@ -1337,13 +1475,21 @@ pub fn generate_mesh(
                 normal: n,
                 tangent: t,
                 uv: uv_,
+                recompute_normals,
             } = i
             {
+                recompute |= *recompute_normals;
                 let index = data_index[*index];
-                position.extend(p.iter().map(|&(i, v)| (i + index, v)));
-                normal.extend(n.iter().map(|&(i, v)| (i + index, v)));
-                tangent.extend(t.iter().map(|&(i, v)| (i + index, v)));
-                uv.extend(uv_.iter().map(|&(i, v)| (i + index, v)));
+                // Follow vertices swapped by reversed winding
+                let f = |i: usize| match (i + index) % 3 {
+                    1 if reverse && !flags.index => i + index + 1,
+                    2 if reverse && !flags.index => i + index - 1,
+                    _ => i + index,
+                };
+                position.extend(p.iter().map(|&(i, v)| (f(i), v)));
+                normal.extend(n.iter().map(|&(i, v)| (f(i), v)));
+                tangent.extend(t.iter().map(|&(i, v)| (f(i), v)));
+                uv.extend(uv_.iter().map(|&(i, v)| (f(i), v)));
             }
         }
 
@@ -1353,6 +1499,17 @@ pub fn generate_mesh(
             data.dedup_by_key(|&mut (i, _)| i);
         }
         orderize(&mut position);
+        if recompute && flags.normal {
+            // Explicit normals take precedence
+            let n = recompute_normals(
+                &buffer[vertex_data.clone()],
+                total_size,
+                normal_offset,
+                &indices,
+                &position,
+            );
+            normal.splice(0..0, n);
+        }
         orderize(&mut normal);
         orderize(&mut tangent);
         orderize(&mut uv);
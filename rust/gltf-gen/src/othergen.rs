@@ -10,19 +10,42 @@ use std::path::Path;
 use anyhow::{bail, Error};
 use camino::{Utf8Path, Utf8PathBuf};
 use image::io::Reader as ImageReader;
-use image::ImageFormat;
+use image::{imageops, DynamicImage, GenericImageView as _, ImageFormat};
 use nalgebra::{Isometry3, Matrix4, Scale, Unit, UnitQuaternion, Vector3};
 
 use super::indexes::Index;
 use super::meshgen::generate_mesh;
 use super::{gltf, parse};
 
-pub fn add_image(path: &Path, gltf: &mut gltf::Gltf, buffer: &mut Vec<u8>) -> Result<(), Error> {
+/// Add image file to buffer.
+///
+/// Returns UV scale mapping the original image into the padded one.
+pub fn add_image(
+    path: &Path,
+    pot: parse::PowerOfTwo,
+    gltf: &mut gltf::Gltf,
+    buffer: &mut Vec<u8>,
+) -> Result<[f32; 2], Error> {
     let start = buffer.len();
     let end;
+    let mut scale = [1.0; 2];
 
     {
-        let img = ImageReader::open(path)?.decode()?;
+        let mut img = ImageReader::open(path)?.decode()?;
+        let (w, h) = img.dimensions();
+        if pot != parse::PowerOfTwo::Any && !(w.is_power_of_two() && h.is_power_of_two()) {
+            if pot == parse::PowerOfTwo::Error {
+                bail!(
+                    "Image file {} size {w}x{h} is not power of two",
+                    path.to_string_lossy()
+                );
+            }
+            let (w_, h_) = (w.next_power_of_two(), h.next_power_of_two());
+            let mut v = DynamicImage::new_rgba8(w_, h_);
+            imageops::replace(&mut v, &img, 0, 0);
+            img = v;
+            scale = [w as f32 / w_ as f32, h as f32 / h_ as f32];
+        }
         let mut cursor = Cursor::new(&mut *buffer);
         cursor.set_position(start as _);
         img.write_to(&mut cursor, ImageFormat::Png)?;
@@ -41,17 +64,36 @@ pub fn add_image(path: &Path, gltf: &mut gltf::Gltf, buffer: &mut Vec<u8>) -> Re
         byte_stride: 0,
     });
 
-    Ok(())
+    Ok(scale)
+}
+
+/// Texture transform extension for padded images.
+fn texture_extensions(
+    scale: [f32; 2],
+    gltf: &mut gltf::Gltf,
+) -> Option<gltf::TextureInfoExtensions> {
+    const EXT_NAME: &str = "KHR_texture_transform";
+    if scale == [1.0; 2] {
+        return None;
+    }
+    if !gltf.extensions_used.iter().any(|v| v == EXT_NAME) {
+        gltf.extensions_used.push(EXT_NAME.to_owned());
+    }
+
+    Some(gltf::TextureInfoExtensions {
+        texture_transform: gltf::TextureTransform { scale },
+    })
 }
 
 fn add_texture<'a>(
     texture: &'a parse::SampleTexture,
     parent: &mut Utf8PathBuf,
+    pot: parse::PowerOfTwo,
     gltf: &mut gltf::Gltf,
     buffer: &mut Vec<u8>,
     index: &mut Index<'a>,
-) -> Result<usize, Error> {
-    let source = index.maybe_add_image(&texture.filename, |file| {
+) -> Result<(usize, Option<gltf::TextureInfoExtensions>), Error> {
+    let (source, scale) = index.maybe_add_image(&texture.filename, |file| {
         let path_ = Utf8Path::new(file);
         if path_.has_root() {
             bail!("Image file {path_} is an absolute path!");
@@ -61,9 +103,9 @@ fn add_texture<'a>(
 
         parent.push(path_);
         let ret = gltf.images.len();
-        add_image(parent.as_std_path(), gltf, buffer)?;
+        let scale = add_image(parent.as_std_path(), pot, gltf, buffer)?;
         parent.pop();
-        Ok(ret)
+        Ok((ret, scale))
     })?;
 
     let (mag_filter, min_filter) = match texture.filter {
@@ -85,11 +127,13 @@ fn add_texture<'a>(
         gltf,
     );
 
-    Ok(index.cache_texture(gltf::Texture { sampler, source }, gltf))
+    let texture = index.cache_texture(gltf::Texture { sampler, source }, gltf);
+    Ok((texture, texture_extensions(scale, gltf)))
 }
 
 fn add_material<'a>(
     parent: &Path,
+    pot: parse::PowerOfTwo,
     material: &'a parse::Material,
     gltf: &mut gltf::Gltf,
     buffer: &mut Vec<u8>,
@@ -129,20 +173,26 @@ fn add_material<'a>(
         ]);
 
         if !material.color_texture.filename.is_empty() {
+            let (i, extensions) =
+                add_texture(&material.color_texture, &mut path, pot, gltf, buffer, index)?;
             v.base_color_texture = Some(gltf::TextureInfo {
-                index: add_texture(&material.color_texture, &mut path, gltf, buffer, index)?,
+                index: i,
+                extensions,
             });
         }
 
         if !material.metallic_roughness_texture.filename.is_empty() {
+            let (i, extensions) = add_texture(
+                &material.metallic_roughness_texture,
+                &mut path,
+                pot,
+                gltf,
+                buffer,
+                index,
+            )?;
             v.metallic_roughness_texture = Some(gltf::TextureInfo {
-                index: add_texture(
-                    &material.metallic_roughness_texture,
-                    &mut path,
-                    gltf,
-                    buffer,
-                    index,
-                )?,
+                index: i,
+                extensions,
             });
         }
 
@@ -150,9 +200,18 @@ fn add_material<'a>(
     }
 
     if !material.normal_texture.filename.is_empty() || material.normal_scale.is_some() {
+        let (i, extensions) = add_texture(
+            &material.normal_texture,
+            &mut path,
+            pot,
+            gltf,
+            buffer,
+            index,
+        )?;
         mat.normal_texture = Some(gltf::NormalTexture {
-            index: add_texture(&material.normal_texture, &mut path, gltf, buffer, index)?,
+            index: i,
             scale: material.normal_scale.unwrap_or(1.0),
+            extensions,
         });
     }
 
@@ -194,7 +253,7 @@ pub fn add_node<'a>(
         let Some(mesh) = data.meshes.get(mesh_name) else {
             bail!("Error at node {name}: no mesh named {mesh_name}")
         };
-        let mut prim = generate_mesh(mesh_name, mesh, gltf, buffer)?;
+        let mut prim = generate_mesh(mesh_name, mesh, data.reverse_winding, gltf, buffer)?;
         if !mesh.material.is_empty() {
             prim.material = Some(match index.named_material.entry(&mesh.material) {
                 Entry::Occupied(v) => *v.get(),
@@ -202,6 +261,7 @@ pub fn add_node<'a>(
                     let ret = *v.insert(gltf.materials.len());
                     add_material(
                         &data.filepath,
+                        data.pot,
                         &data.materials[&mesh.material],
                         gltf,
                         buffer,
@@ -214,7 +274,18 @@ pub fn add_node<'a>(
         index.named_mesh.insert(mesh_name, prim.clone());
         Ok(prim)
     };
-    let primitives = node.mesh.iter().map(f).collect::<Result<Vec<_>, _>>()?;
+    let mesh = match &node.instances {
+        Some(_) if !node.mesh.is_empty() => {
+            bail!("Error at node {name}: node has both mesh and instances")
+        }
+        Some(v) if v.mesh.is_empty() => bail!("Error at node {name}: instances has no mesh"),
+        Some(v) if v.transforms.is_empty() => {
+            bail!("Error at node {name}: instances has no transforms")
+        }
+        Some(v) => &v.mesh,
+        None => &node.mesh,
+    };
+    let primitives = mesh.iter().map(f).collect::<Result<Vec<_>, _>>()?;
     if !primitives.is_empty() {
         ret.mesh = Some(gltf.meshes.len());
         gltf.meshes.push(gltf::Mesh {
@@ -222,6 +293,9 @@ pub fn add_node<'a>(
             primitives,
         });
     }
+    if let Some(v) = &node.instances {
+        ret.extensions = Some(add_instances(&v.transforms, gltf, buffer));
+    }
 
     let f = |child: &'a String| add_node(data, child, gltf, buffer, index);
     ret.children = node.children.iter().map(f).collect::<Result<Vec<_>, _>>()?;
@@ -232,6 +306,84 @@ pub fn add_node<'a>(
     Ok(v)
 }
 
+fn add_instances(
+    transforms: &[parse::TransformTRS],
+    gltf: &mut gltf::Gltf,
+    buffer: &mut Vec<u8>,
+) -> gltf::NodeExtensions {
+    const EXT_NAME: &str = "EXT_mesh_gpu_instancing";
+    if !gltf.extensions_used.iter().any(|v| v == EXT_NAME) {
+        gltf.extensions_used.push(EXT_NAME.to_owned());
+    }
+
+    fn f(
+        gltf: &mut gltf::Gltf,
+        buffer: &mut Vec<u8>,
+        count: usize,
+        type_: gltf::AccessorType,
+        data: impl IntoIterator<Item = f32>,
+    ) -> Option<usize> {
+        let ret = gltf.accessors.len();
+        gltf.accessors.push(gltf::Accessor {
+            buffer_view: Some(gltf.buffer_views.len()),
+            byte_offset: 0,
+            component_type: gltf::ComponentType::FLOAT,
+            normalized: false,
+            count,
+            type_,
+            sparse: None,
+        });
+
+        buffer.resize((buffer.len() + 3) & !3, 0);
+        let byte_offset = buffer.len();
+        buffer.extend(data.into_iter().flat_map(|v| v.to_le_bytes()));
+        gltf.buffer_views.push(gltf::BufferView {
+            buffer: 0,
+            byte_offset,
+            byte_length: buffer.len() - byte_offset,
+            byte_stride: 0,
+        });
+        Some(ret)
+    }
+
+    let count = transforms.len();
+    let attributes = gltf::InstanceAttribute {
+        translation: f(
+            gltf,
+            buffer,
+            count,
+            gltf::AccessorType::VEC3,
+            transforms.iter().flat_map(|t| {
+                let v = &t.translation.vector;
+                [v.x, v.y, v.z]
+            }),
+        ),
+        rotation: f(
+            gltf,
+            buffer,
+            count,
+            gltf::AccessorType::VEC4,
+            transforms.iter().flat_map(|t| {
+                let v = t.rotation.quaternion();
+                [v.i, v.j, v.k, v.w]
+            }),
+        ),
+        scale: f(
+            gltf,
+            buffer,
+            count,
+            gltf::AccessorType::VEC3,
+            transforms
+                .iter()
+                .flat_map(|t| [t.scale.x, t.scale.y, t.scale.z]),
+        ),
+    };
+
+    gltf::NodeExtensions {
+        mesh_gpu_instancing: gltf::MeshGpuInstancing { attributes },
+    }
+}
+
 pub fn add_skeleton<'a>(
     skeleton: &parse::Skeleton,
     name: &'a str,
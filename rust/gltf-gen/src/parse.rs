@@ -24,6 +24,19 @@ pub struct Data {
     pub root_node: String,
     #[serde(skip)]
     pub filepath: PathBuf,
+    #[serde(skip)]
+    pub pot: PowerOfTwo,
+    #[serde(skip)]
+    pub reverse_winding: bool,
+}
+
+/// Power-of-two texture size enforcement.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PowerOfTwo {
+    #[default]
+    Any,
+    Error,
+    Pad,
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,6 +55,15 @@ pub struct Node {
     pub mesh: Vec<String>,
     #[serde(default)]
     pub skin: Option<String>,
+    #[serde(default)]
+    pub instances: Option<Instances>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Instances {
+    #[serde(deserialize_with = "string_or_strings")]
+    pub mesh: Vec<String>,
+    pub transforms: Vec<TransformTRS>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -55,6 +77,9 @@ pub struct Mesh {
     pub material: String,
     #[serde(default)]
     pub blend: Vec<Vec<BlendData>>,
+    /// Split vertices per triangle and use face normals.
+    #[serde(default)]
+    pub flat_shading: bool,
 }
 
 #[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq, Hash)]
@@ -263,6 +288,9 @@ pub enum BlendData {
         tangent: Vec<(usize, Vector3<f32>)>,
         #[serde(default)]
         uv: Vec<(usize, Vector2<f32>)>,
+        /// Derive normals from shifted positions, instead of given ones.
+        #[serde(default)]
+        recompute_normals: bool,
     },
 }
 
@@ -0,0 +1,304 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::*;
+
+use anyhow::Error;
+use serde_json::json;
+
+fn generate(data: serde_json::Value) -> Result<(gltf::Gltf, Vec<u8>), Error> {
+    generate_data(serde_json::from_value(data)?)
+}
+
+fn generate_data(data: parse::Data) -> Result<(gltf::Gltf, Vec<u8>), Error> {
+    let mut index = indexes::Index::default();
+    let mut gltf = gltf::Gltf::default();
+    let mut buffer = Vec::new();
+
+    othergen::add_node(&data, &data.root_node, &mut gltf, &mut buffer, &mut index)?;
+    othergen::bind_skins(&data, &mut gltf, &mut buffer, &mut index)?;
+
+    Ok((gltf, buffer))
+}
+
+fn triangle() -> serde_json::Value {
+    json!({
+        "material": "",
+        "data": [{
+            "type": "triangles",
+            "position": [[0, 0, 0], [1, 0, 0], [0, 1, 0]],
+        }],
+    })
+}
+
+fn read_f32(buffer: &[u8], view: &gltf::BufferView) -> Vec<f32> {
+    buffer[view.byte_offset..view.byte_offset + view.byte_length]
+        .chunks_exact(4)
+        .map(|v| f32::from_le_bytes(v.try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_instances() -> Result<(), Error> {
+    let transforms = (0..100)
+        .map(|i| json!({"translation": [i, 0, 0]}))
+        .collect::<Vec<_>>();
+    let (gltf, buffer) = generate(json!({
+        "meshes": {"Tree": triangle()},
+        "nodes": {
+            "Forest": {
+                "instances": {
+                    "mesh": "Tree",
+                    "transforms": transforms,
+                },
+            },
+        },
+        "materials": {},
+        "skeletons": {},
+        "animations": {},
+        "root_node": "Forest",
+    }))?;
+
+    assert_eq!(gltf.meshes.len(), 1);
+    assert_eq!(gltf.nodes.len(), 1);
+    assert_eq!(gltf.extensions_used, ["EXT_mesh_gpu_instancing"]);
+
+    let node = &gltf.nodes[0];
+    assert_eq!(node.mesh, Some(0));
+    let attrs = &node
+        .extensions
+        .as_ref()
+        .unwrap()
+        .mesh_gpu_instancing
+        .attributes;
+    for (a, t) in [
+        (attrs.translation, gltf::AccessorType::VEC3),
+        (attrs.rotation, gltf::AccessorType::VEC4),
+        (attrs.scale, gltf::AccessorType::VEC3),
+    ] {
+        let a = &gltf.accessors[a.unwrap()];
+        assert_eq!(a.count, 100);
+        assert_eq!(a.type_, t);
+        assert_eq!(a.byte_offset % 4, 0);
+    }
+
+    let a = &gltf.accessors[attrs.translation.unwrap()];
+    let v = read_f32(&buffer, &gltf.buffer_views[a.buffer_view.unwrap()]);
+    for (i, v) in v.chunks_exact(3).enumerate() {
+        assert_eq!(v, [i as f32, 0., 0.]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reverse_winding() -> Result<(), Error> {
+    let mut indexed = triangle();
+    indexed["data"][0]["index"] = json!([0, 1, 2]);
+    let mut data: parse::Data = serde_json::from_value(json!({
+        "meshes": {"Plain": triangle(), "Indexed": indexed},
+        "nodes": {"Root": {"mesh": ["Plain", "Indexed"]}},
+        "materials": {},
+        "skeletons": {},
+        "animations": {},
+        "root_node": "Root",
+    }))?;
+    data.reverse_winding = true;
+    let (gltf, buffer) = generate_data(data)?;
+
+    let prim = &gltf.meshes[0].primitives;
+    let position = |p: &gltf::MeshPrimitive| {
+        let a = &gltf.accessors[p.attributes.position.unwrap()];
+        let v = &gltf.buffer_views[a.buffer_view.unwrap()];
+        read_f32(&buffer, v)
+            .chunks_exact(v.byte_stride / 4)
+            .map(|v| [v[0], v[1], v[2]])
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        position(&prim[0]),
+        [[0., 0., 0.], [0., 1., 0.], [1., 0., 0.]]
+    );
+    assert_eq!(
+        position(&prim[1]),
+        [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]]
+    );
+
+    let v = &gltf.buffer_views[gltf.accessors[prim[1].indices.unwrap()]
+        .buffer_view
+        .unwrap()];
+    let i = buffer[v.byte_offset..v.byte_offset + v.byte_length]
+        .chunks_exact(4)
+        .map(|v| u32::from_le_bytes(v.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(i, [0, 2, 1]);
+
+    Ok(())
+}
+
+#[test]
+fn test_instances_with_mesh() -> Result<(), Error> {
+    let ret = generate(json!({
+        "meshes": {"Tree": triangle()},
+        "nodes": {
+            "Forest": {
+                "mesh": ["Tree"],
+                "instances": {
+                    "mesh": "Tree",
+                    "transforms": [{}],
+                },
+            },
+        },
+        "materials": {},
+        "skeletons": {},
+        "animations": {},
+        "root_node": "Forest",
+    }));
+    assert!(ret.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_instances_empty() -> Result<(), Error> {
+    let err = generate(json!({
+        "meshes": {"Tree": triangle()},
+        "nodes": {
+            "Forest": {
+                "instances": {
+                    "mesh": "Tree",
+                    "transforms": [],
+                },
+            },
+        },
+        "materials": {},
+        "skeletons": {},
+        "animations": {},
+        "root_node": "Forest",
+    }))
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("Forest"));
+
+    Ok(())
+}
+
+fn write_image(name: &str, w: u32, h: u32) -> Result<std::path::PathBuf, Error> {
+    let path = std::env::temp_dir().join(name);
+    image::RgbaImage::new(w, h).save(&path)?;
+    Ok(path)
+}
+
+#[test]
+fn test_image_pot() -> Result<(), Error> {
+    let path = write_image("gltf-gen-test-pot.png", 6, 5)?;
+
+    let mut gltf = gltf::Gltf::default();
+    let mut buffer = Vec::new();
+    othergen::add_image(&path, parse::PowerOfTwo::Any, &mut gltf, &mut buffer)?;
+    assert_eq!(gltf.images.len(), 1);
+
+    let err = othergen::add_image(&path, parse::PowerOfTwo::Error, &mut gltf, &mut buffer)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("gltf-gen-test-pot.png"));
+    assert_eq!(gltf.images.len(), 1);
+
+    let mut buffer = Vec::new();
+    let scale = othergen::add_image(&path, parse::PowerOfTwo::Pad, &mut gltf, &mut buffer)?;
+    let img = image::load_from_memory(&buffer)?;
+    assert_eq!((img.width(), img.height()), (8, 8));
+    assert_eq!(scale, [6. / 8., 5. / 8.]);
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn test_flat_shading() -> Result<(), Error> {
+    let (gltf, buffer) = generate(json!({
+        "meshes": {"Fold": {
+            "material": "",
+            "data": [{
+                "type": "triangles",
+                "position": [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 1]],
+                "index": [0, 1, 2, 1, 3, 2],
+            }],
+            "flat_shading": true,
+        }},
+        "nodes": {"Fold": {"mesh": ["Fold"]}},
+        "materials": {},
+        "skeletons": {},
+        "animations": {},
+        "root_node": "Fold",
+    }))?;
+
+    let prim = &gltf.meshes[0].primitives[0];
+    let a = &gltf.accessors[prim.attributes.normal.unwrap()];
+    assert_eq!(a.count, 6);
+    let v = read_f32(&buffer, &gltf.buffer_views[a.buffer_view.unwrap()]);
+    let a = &gltf.accessors[prim.indices.unwrap()];
+    let view = &gltf.buffer_views[a.buffer_view.unwrap()];
+    let index = buffer[view.byte_offset..view.byte_offset + view.byte_length]
+        .chunks_exact(4)
+        .map(|v| u32::from_le_bytes(v.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    // No vertex is shared
+    assert_eq!(index, [0, 1, 2, 3, 4, 5]);
+
+    let s = 1. / 3f32.sqrt();
+    let expected = [[0., 0., 1.], [-s, -s, s]];
+    // Each vertex is position then normal
+    for (i, v) in v.chunks_exact(6).enumerate() {
+        for (n, e) in v[3..].iter().zip(expected[i / 3]) {
+            assert!((n - e).abs() < 1e-5, "{n} != {e}");
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_blend_recompute_normals() -> Result<(), Error> {
+    let (gltf, buffer) = generate(json!({
+        "meshes": {"Quad": {
+            "material": "",
+            "data": [{
+                "type": "triangles",
+                "position": [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]],
+                "normal": [[0, 0, 1]],
+                "index": [0, 1, 3, 0, 3, 2],
+            }],
+            "blend": [[{
+                "type": "shift_vertex",
+                "position": [[3, [0, 0, 1]]],
+                "recompute_normals": true,
+            }]],
+        }},
+        "nodes": {"Quad": {"mesh": ["Quad"]}},
+        "materials": {},
+        "skeletons": {},
+        "animations": {},
+        "root_node": "Quad",
+    }))?;
+
+    let target = &gltf.meshes[0].primitives[0].targets[0];
+    let a = &gltf.accessors[target.normal.unwrap()];
+    let sparse = a.sparse.as_ref().unwrap();
+    assert_eq!(sparse.count, 4);
+    let v = read_f32(&buffer, &gltf.buffer_views[sparse.values.buffer_view]);
+
+    // Hand computed from the two displaced faces
+    let (a, b) = (1. / 6f32.sqrt(), 1. / 2f32.sqrt());
+    let expected = [[-a, -a, 2. * a], [0., -b, b], [-b, 0., b], [-a, -a, 2. * a]];
+    for (v, e) in v.chunks_exact(3).zip(expected) {
+        // Deltas are relative to base normal
+        let n = [v[0], v[1], v[2] + 1.];
+        for (n, e) in n.iter().zip(e) {
+            assert!((n - e).abs() < 1e-5, "{n} != {e}");
+        }
+    }
+
+    Ok(())
+}
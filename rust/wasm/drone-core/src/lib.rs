@@ -49,7 +49,7 @@ impl State {
 
 pub const INVENTORY_SIZE: usize = 9;
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct Drone {
     pub x: usize,
@@ -57,7 +57,19 @@ pub struct Drone {
     pub z: usize,
 
     pub command: Command,
+    pub inventory_size: u8,
     pub inventory: [Inventory; INVENTORY_SIZE],
+    pub result: CommandResult,
+    pub facing: Dir,
+    pub drop_policy: DropPolicy,
+    /// Next slot for round robin drop policy.
+    pub drop_cursor: u8,
+    /// Ticks left to wait, ignoring commands.
+    pub wait: u16,
+    /// Items moved per tick by transfer commands, zero is unlimited.
+    pub transfer_rate: u8,
+    /// Distance of break and place target, zero counts as one.
+    pub reach: u8,
 }
 
 impl Drone {
@@ -67,9 +79,27 @@ impl Drone {
             y: 0,
             z: 0,
             command: Command::Noop,
+            inventory_size: INVENTORY_SIZE as _,
             inventory: [Inventory::new(None, 0); INVENTORY_SIZE],
+            result: CommandResult::None,
+            facing: Dir::Front,
+            drop_policy: DropPolicy::Stack,
+            drop_cursor: 0,
+            wait: 0,
+            transfer_rate: 0,
+            reach: 1,
         }
     }
+
+    pub fn inventory(&self) -> &[Inventory] {
+        &self.inventory[..(self.inventory_size as usize).min(INVENTORY_SIZE)]
+    }
+}
+
+impl Default for Drone {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -83,6 +113,44 @@ pub enum Command {
     SendItem(Dir, u8),
     RecvItem(Dir, u8),
     Restack,
+    Swap(Dir),
+    Harvest(Dir),
+    Paint(Dir, u8),
+    Look(Dir),
+    Wait(u16),
+    TransferAll(Dir),
+}
+
+/// Where picked up items go in drone inventory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DropPolicy {
+    /// Top up existing stacks first, then empty slots.
+    #[default]
+    Stack,
+    /// First slot in order that can hold the item.
+    Ordered,
+    /// Like Ordered, but start after the last slot put into.
+    RoundRobin,
+}
+
+/// Outcome of the last executed command.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CommandResult {
+    /// No command was given.
+    #[default]
+    None,
+    Ok,
+    OutOfBounds,
+    /// Target is obstructed, by block or another drone.
+    Blocked,
+    /// Inventory slot is invalid or empty.
+    NoItem,
+    /// Nothing to act upon at target.
+    NoTarget,
+    /// Drone is waiting, command is ignored.
+    Waiting,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -99,21 +167,34 @@ pub enum Dir {
 }
 
 impl Dir {
+    /// Unit step (x, y, z) of direction.
+    pub const fn offset(&self) -> (isize, isize, isize) {
+        match self {
+            Self::Noop => (0, 0, 0),
+            Self::Up => (0, 1, 0),
+            Self::Down => (0, -1, 0),
+            Self::Left => (1, 0, 0),
+            Self::Right => (-1, 0, 0),
+            Self::Back => (0, 0, 1),
+            Self::Front => (0, 0, -1),
+        }
+    }
+
     pub fn move_coord(
         &self,
         size: &(usize, usize, usize),
         coord: (usize, usize, usize),
     ) -> Option<(usize, usize, usize)> {
-        Some(match self {
-            Self::Noop => coord,
-            Self::Up if coord.1 + 1 < size.1 => (coord.0, coord.1 + 1, coord.2),
-            Self::Down if coord.1 > 0 => (coord.0, coord.1 - 1, coord.2),
-            Self::Left if coord.0 + 1 < size.0 => (coord.0 + 1, coord.1, coord.2),
-            Self::Right if coord.0 > 0 => (coord.0 - 1, coord.1, coord.2),
-            Self::Back if coord.2 + 1 < size.2 => (coord.0, coord.1, coord.2 + 1),
-            Self::Front if coord.2 > 0 => (coord.0, coord.1, coord.2 - 1),
-            _ => return None,
-        })
+        fn f(c: usize, d: isize, s: usize) -> Option<usize> {
+            c.checked_add_signed(d).filter(|&v| v < s)
+        }
+
+        let (dx, dy, dz) = self.offset();
+        Some((
+            f(coord.0, dx, size.0)?,
+            f(coord.1, dy, size.1)?,
+            f(coord.2, dz, size.2)?,
+        ))
     }
 }
 
@@ -140,6 +221,7 @@ impl fmt::Display for Dir {
 pub struct Inventory {
     pub item_id: Option<NonZeroU16>,
     pub count: u8,
+    pub data_hash: u8,
 }
 
 impl Inventory {
@@ -147,6 +229,7 @@ impl Inventory {
         Self {
             count: if item_id.is_none() { 0 } else { count },
             item_id,
+            data_hash: 0,
         }
     }
 }
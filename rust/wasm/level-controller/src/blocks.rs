@@ -7,7 +7,8 @@ use std::num::NonZeroU16;
 use ndarray::{s, Array3, Dimension};
 use rand::Rng;
 
-use super::drone::Inventory;
+use super::drone::{Dir, Inventory};
+use super::{META_MASK, META_SHIFT};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BlockType {
@@ -16,11 +17,50 @@ pub enum BlockType {
     Blade,
 }
 
+pub const CROP: u8 = 3;
+pub const CROP_SEED: u16 = 3;
+/// Growth stage (in metadata) of a fully grown crop.
+pub const CROP_MATURE: u8 = 7;
+
+pub const fn is_mature_crop(b: u32) -> bool {
+    ((b & 0xff) as u8 == CROP) && (block_meta(b) >= CROP_MATURE)
+}
+
+/// Direction of neighbour a block must rest on, if any.
+///
+/// Neighbour must be a full block.
+pub const fn placement_support(ty: u8) -> Option<Dir> {
+    match ty {
+        CROP => Some(Dir::Down),
+        _ => None,
+    }
+}
+
+/// Whether block at c has its required support.
+pub fn is_supported(ty: u8, c: (usize, usize, usize), data: &Array3<u32>) -> bool {
+    let Some(dir) = placement_support(ty) else {
+        return true;
+    };
+    dir.move_coord(&data.raw_dim().into_pattern(), c)
+        .is_some_and(|c| block_type((data[c] & 0xff) as _) == BlockType::Full)
+}
+
+pub const fn block_meta(b: u32) -> u8 {
+    ((b & META_MASK) >> META_SHIFT) as u8
+}
+
+pub const fn set_block_meta(b: u32, meta: u8) -> u32 {
+    (b & !META_MASK) | (((meta as u32) << META_SHIFT) & META_MASK)
+}
+
 macro_rules! blocks {
-    (#dist $r:ident ..) => {$r.gen(0..Inventory::MAX_STACK)};
+    (#dist $r:ident ..) => {$r.gen_range(0..Inventory::MAX_STACK)};
     (#dist $r:ident $n:literal) => {$n};
-    (#dist $r:ident ..$b:literal) => {$r.gen(0..$b)};
-    (#dist $r:ident $a:literal..$b:literal) => {$r.gen($a..$b)};
+    (#dist $r:ident ..$b:literal) => {$r.gen_range(0..$b)};
+    (#dist $r:ident $a:literal..$b:literal) => {$r.gen_range($a..$b)};
+    (#dist $r:ident ($($t:tt)*)) => {blocks!(#dist $r $($t)*)};
+    (#tick _) => {false};
+    (#tick $rt:tt) => {true};
     ($t:ident $ty:tt $id:literal _) => {};
     (uv $ty:ident $id:literal [$x:literal, $y:literal]) => {
         if $ty == $id {
@@ -75,6 +115,13 @@ macro_rules! blocks {
             }
         }
 
+        pub const fn is_tickable(ty: u8) -> bool {
+            match ty {
+                $($id => blocks!(#tick $rt),)*
+                _ => false,
+            }
+        }
+
         pub const fn block_uv(_ty: u8) -> [usize; 2] {
             $(blocks!{uv _ty $id $uv})*
             [0, 0]
@@ -90,20 +137,24 @@ macro_rules! blocks {
             T::default()
         }
 
-        pub const fn block_place(_it: u16, _c: (usize, usize, usize), _data: &Array3<u32>) -> Option<u8> {
+        pub fn block_place(_it: u16, _c: (usize, usize, usize), _data: &Array3<u32>) -> Option<u8> {
             $(blocks!{place (_it _c _data) $id $p})*
             None
         }
 
-        pub fn random_tick<R, F>(_r: &mut R, mut c: F, _data: &mut Array3<u32>)
+        pub fn random_tick<R, F, S>(_r: &mut R, mut c: F, skip: S, _data: &mut Array3<u32>)
         where
             R: Rng,
             F: FnMut(&mut R) -> Option<(usize, usize, usize)>,
+            S: Fn(u8) -> bool,
         {
             while let Some(c) = c(&mut *_r) {
                 let Some(&_b) = _data.get(c) else {
                     continue;
                 };
+                if skip((_b & 0xff) as u8) {
+                    continue;
+                }
 
                 $(blocks!{tick (_b _r c _data) $id $rt})*
             }
@@ -155,4 +206,17 @@ blocks! {
 
         None
     })),
+    // Crop
+    3 : (Blade, [2, 0], [3 => (1..4)], (3 => |c, d| {
+        let (x, y, z) = c;
+        (y > 0) && matches!(d[(x, y - 1, z)] & 0xff, 1 | 2)
+    }), (|r, c, d| {
+        let b = d[c];
+        let m = block_meta(b);
+        if (m >= CROP_MATURE) || (r.gen_range(0..4u8) >= 1) {
+            return None;
+        }
+
+        Some(set_block_meta(b, m + 1))
+    })),
 }
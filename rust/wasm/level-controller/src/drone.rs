@@ -3,16 +3,21 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::num::NonZeroU16;
 
 use ndarray::{Array3, Dimension};
+use rand::Rng;
 
-use super::blocks::{block_drops, block_place, block_type, is_valid, BlockType};
-use super::{Mesh, State, OCCUPIED_FLAG};
+use super::blocks::{
+    block_drops, block_place, block_type, is_mature_crop, is_supported, is_valid, set_block_meta,
+    BlockType, CROP, CROP_SEED,
+};
+use super::{Event, Mesh, State, META_MASK, OCCUPIED_FLAG};
 
-const INVENTORY_SIZE: usize = 9;
+pub const INVENTORY_SIZE: usize = 9;
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Hash)]
 #[repr(C)]
 pub struct Drone {
     pub x: usize,
@@ -20,10 +25,74 @@ pub struct Drone {
     pub z: usize,
 
     pub command: Command,
+    pub inventory_size: u8,
     pub inventory: [Inventory; INVENTORY_SIZE],
+    pub result: CommandResult,
+    pub facing: Dir,
+    pub drop_policy: DropPolicy,
+    /// Next slot for round robin drop policy.
+    pub drop_cursor: u8,
+    /// Ticks left to wait, ignoring commands.
+    pub wait: u16,
+    /// Items moved per tick by transfer commands, zero is unlimited.
+    pub transfer_rate: u8,
+    /// Distance of break and place target, zero counts as one.
+    pub reach: u8,
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+impl Default for Drone {
+    fn default() -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            z: 0,
+            command: Command::Noop,
+            inventory_size: INVENTORY_SIZE as _,
+            inventory: [Inventory::default(); INVENTORY_SIZE],
+            result: CommandResult::None,
+            facing: Dir::Front,
+            drop_policy: DropPolicy::Stack,
+            drop_cursor: 0,
+            wait: 0,
+            transfer_rate: 0,
+            reach: 1,
+        }
+    }
+}
+
+impl Drone {
+    pub fn inventory(&self) -> &[Inventory] {
+        &self.inventory[..(self.inventory_size as usize).min(INVENTORY_SIZE)]
+    }
+
+    pub fn inventory_mut(&mut self) -> &mut [Inventory] {
+        &mut self.inventory[..(self.inventory_size as usize).min(INVENTORY_SIZE)]
+    }
+
+    /// Change inventory size, moving items out of removed slots.
+    ///
+    /// Returns false and leaves drone unchanged if they don't fit.
+    pub fn resize_inventory<F>(&mut self, size: u8, max: F) -> bool
+    where
+        F: Fn(Option<NonZeroU16>) -> u8,
+    {
+        let size = size.min(INVENTORY_SIZE as _);
+        let mut inv = self.inventory;
+        let (keep, removed) = inv.split_at_mut(size as usize);
+        for s in removed {
+            Inventory::try_put_one_with(keep, s, &max);
+            if s.item_id.is_some() {
+                return false;
+            }
+        }
+
+        self.inventory = inv;
+        self.inventory_size = size;
+        true
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Hash)]
 #[repr(u8)]
 pub enum Command {
     #[default]
@@ -34,9 +103,47 @@ pub enum Command {
     SendItem(Dir, u8),
     RecvItem(Dir, u8),
     Restack,
+    Swap(Dir),
+    Harvest(Dir),
+    Paint(Dir, u8),
+    Look(Dir),
+    Wait(u16),
+    TransferAll(Dir),
+}
+
+/// Where picked up items go in drone inventory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum DropPolicy {
+    /// Top up existing stacks first, then empty slots.
+    #[default]
+    Stack,
+    /// First slot in order that can hold the item.
+    Ordered,
+    /// Like Ordered, but start after the last slot put into.
+    RoundRobin,
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Outcome of the last executed command.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum CommandResult {
+    /// No command was given.
+    #[default]
+    None,
+    Ok,
+    OutOfBounds,
+    /// Target is obstructed, by block or another drone.
+    Blocked,
+    /// Inventory slot is invalid or empty.
+    NoItem,
+    /// Nothing to act upon at target.
+    NoTarget,
+    /// Drone is waiting, command is ignored.
+    Waiting,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Dir {
     #[default]
@@ -50,29 +157,44 @@ pub enum Dir {
 }
 
 impl Dir {
+    /// Unit step (x, y, z) of direction.
+    pub const fn offset(&self) -> (isize, isize, isize) {
+        match self {
+            Self::Noop => (0, 0, 0),
+            Self::Up => (0, 1, 0),
+            Self::Down => (0, -1, 0),
+            Self::Left => (1, 0, 0),
+            Self::Right => (-1, 0, 0),
+            Self::Back => (0, 0, 1),
+            Self::Front => (0, 0, -1),
+        }
+    }
+
     pub fn move_coord(
         &self,
         size: &(usize, usize, usize),
         coord: (usize, usize, usize),
     ) -> Option<(usize, usize, usize)> {
-        Some(match self {
-            Self::Noop => coord,
-            Self::Up if coord.1 + 1 < size.1 => (coord.0, coord.1 + 1, coord.2),
-            Self::Down if coord.1 > 0 => (coord.0, coord.1 - 1, coord.2),
-            Self::Left if coord.0 + 1 < size.0 => (coord.0 + 1, coord.1, coord.2),
-            Self::Right if coord.0 > 0 => (coord.0 - 1, coord.1, coord.2),
-            Self::Back if coord.2 + 1 < size.2 => (coord.0, coord.1, coord.2 + 1),
-            Self::Front if coord.2 > 0 => (coord.0, coord.1, coord.2 - 1),
-            _ => return None,
-        })
+        fn f(c: usize, d: isize, s: usize) -> Option<usize> {
+            c.checked_add_signed(d).filter(|&v| v < s)
+        }
+
+        let (dx, dy, dz) = self.offset();
+        Some((
+            f(coord.0, dx, size.0)?,
+            f(coord.1, dy, size.1)?,
+            f(coord.2, dz, size.2)?,
+        ))
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Hash)]
 #[repr(C)]
 pub struct Inventory {
     pub item_id: Option<NonZeroU16>,
     pub count: u8,
+    /// Extra item data. Items only stack if their data matches.
+    pub data_hash: u8,
 }
 
 impl Inventory {
@@ -82,15 +204,53 @@ impl Inventory {
         Self {
             count: if item_id.is_none() { 0 } else { count },
             item_id,
+            data_hash: 0,
+        }
+    }
+
+    pub const fn with_data_hash(mut self, data_hash: u8) -> Self {
+        self.data_hash = data_hash;
+        self
+    }
+
+    /// Stacking key, same key can be stacked together.
+    pub const fn key(&self) -> (Option<NonZeroU16>, u8) {
+        match self.item_id {
+            None => (None, 0),
+            v => (v, self.data_hash),
+        }
+    }
+
+    /// Split off at most n items.
+    pub fn split(&mut self, n: u8) -> Self {
+        let n = self.count.min(n);
+        self.count -= n;
+        Self { count: n, ..*self }
+    }
+
+    /// Put back remainder of items split off from this.
+    pub fn unsplit(&mut self, part: Self) {
+        self.count += part.count;
+        if self.count == 0 {
+            self.item_id = None;
         }
     }
 
     pub fn try_put_one(this: &mut [Self], src: &mut Self) {
+        Self::try_put_one_with(this, src, |_| Self::MAX_STACK)
+    }
+
+    /// Like [`Self::try_put_one`], with stack size given by `max`.
+    pub fn try_put_one_with<F>(this: &mut [Self], src: &mut Self, max: F)
+    where
+        F: Fn(Option<NonZeroU16>) -> u8,
+    {
+        let m = max(src.item_id);
         for d in &mut *this {
-            if d.item_id != src.item_id {
+            if d.key() != src.key() {
                 continue;
             }
-            let n = src.count.min(Self::MAX_STACK - d.count);
+            let n = src.count.min(m.saturating_sub(d.count));
             d.count += n;
             src.count -= n;
             if src.count == 0 {
@@ -103,13 +263,60 @@ impl Inventory {
             if d.item_id.is_some() {
                 continue;
             }
-            (d.item_id, d.count) = (src.item_id, src.count);
-            (src.item_id, src.count) = (None, 0);
-            return;
+            let n = src.count.min(m);
+            *d = *src;
+            d.count = n;
+            src.count -= n;
+            if src.count == 0 {
+                src.item_id = None;
+                return;
+            }
         }
     }
 
-    pub fn try_put_many(this: &mut [Self], mut src: &mut [Self]) -> bool {
+    /// Put item into slots in order, starting at slot start and wrapping around.
+    ///
+    /// Returns the last slot put into.
+    pub fn try_put_from<F>(this: &mut [Self], src: &mut Self, start: usize, max: F) -> Option<usize>
+    where
+        F: Fn(Option<NonZeroU16>) -> u8,
+    {
+        let m = max(src.item_id);
+        let mut last = None;
+        for k in 0..this.len() {
+            if src.item_id.is_none() || src.count == 0 {
+                break;
+            }
+            let j = (start + k) % this.len();
+            let d = &mut this[j];
+            if d.item_id.is_none() {
+                (d.item_id, d.count, d.data_hash) = (src.item_id, 0, src.data_hash);
+            } else if d.key() != src.key() {
+                continue;
+            }
+            let n = src.count.min(m.saturating_sub(d.count));
+            d.count += n;
+            src.count -= n;
+            if n > 0 {
+                last = Some(j);
+            }
+        }
+
+        if src.count == 0 {
+            src.item_id = None;
+        }
+        last
+    }
+
+    pub fn try_put_many(this: &mut [Self], src: &mut [Self]) -> bool {
+        Self::try_put_many_with(this, src, |_| Self::MAX_STACK)
+    }
+
+    /// Like [`Self::try_put_many`], with stack size given by `max`.
+    pub fn try_put_many_with<F>(this: &mut [Self], mut src: &mut [Self], max: F) -> bool
+    where
+        F: Fn(Option<NonZeroU16>) -> u8,
+    {
         if src.iter().all(|v| v.item_id.is_none()) {
             return true;
         }
@@ -119,7 +326,7 @@ impl Inventory {
                 s.item_id = None;
             }
         }
-        src.sort_unstable_by_key(|v| v.item_id);
+        src.sort_unstable_by_key(Self::key);
         let i = src.partition_point(|v| v.item_id.is_none());
         src = &mut src[i..];
         if src.is_empty() {
@@ -131,11 +338,14 @@ impl Inventory {
                 continue;
             }
 
-            let i = src.partition_point(|v| v.item_id < d.item_id);
-            let mut n = Self::MAX_STACK - d.count;
+            let i = src.partition_point(|v| v.key() < d.key());
+            let mut n = max(d.item_id).saturating_sub(d.count);
+            if n == 0 {
+                continue;
+            }
             let mut j = 0;
             for s in &mut src[i..] {
-                if s.item_id != d.item_id {
+                if s.key() != d.key() {
                     continue;
                 }
                 let n_ = s.count.min(n);
@@ -164,10 +374,12 @@ impl Inventory {
             if d.item_id.is_some() {
                 continue;
             }
-            d.count = 0;
-            let mut n = Self::MAX_STACK;
+            (d.item_id, d.count, d.data_hash) = (src[0].item_id, 0, src[0].data_hash);
+            let mut n = max(d.item_id);
             while let Some(s) = src.first_mut() {
-                d.item_id = s.item_id;
+                if s.key() != d.key() {
+                    break;
+                }
                 let n_ = s.count.min(n);
                 d.count += n_;
                 n -= n_;
@@ -235,7 +447,7 @@ impl MoveIndex {
     }
 }
 
-fn mark_dirty(
+pub fn mark_dirty(
     mesh: &mut Array3<Mesh>,
     chunks_size: usize,
     (mut x, mut y, mut z): (usize, usize, usize),
@@ -248,9 +460,46 @@ fn mark_dirty(
     }
 }
 
+/// Whether cell is in a frozen chunk.
+pub fn is_frozen(
+    frozen: &Array3<bool>,
+    chunks_size: usize,
+    (x, y, z): (usize, usize, usize),
+) -> bool {
+    frozen
+        .get((x / chunks_size, y / chunks_size, z / chunks_size))
+        .is_some_and(|&v| v)
+}
+
 pub fn execute_commands(state: &mut State) {
     let size = state.data.raw_dim().into_pattern();
 
+    for d in &mut state.drones {
+        if d.wait > 0 {
+            d.wait -= 1;
+            d.command = Command::Noop;
+            d.result = CommandResult::Waiting;
+            continue;
+        }
+        d.result = match d.command {
+            Command::Noop => CommandResult::None,
+            _ => CommandResult::Ok,
+        };
+        // Current tick counts as the first one
+        if let Command::Wait(n) = d.command {
+            d.wait = n.saturating_sub(1);
+            d.command = Command::Noop;
+        }
+    }
+
+    if state
+        .drones
+        .iter()
+        .any(|d| matches!(d.command, Command::Swap(_)))
+    {
+        swap_drone(state, &size);
+    }
+
     let mut has_move = false;
     for (((i, d), m), r) in state
         .drones
@@ -269,9 +518,13 @@ pub fn execute_commands(state: &mut State) {
         let mut c = None;
         if let Command::Move(dir) = d.command {
             if dir != Dir::Noop {
-                c = dir
-                    .move_coord(&size, (d.x, d.y, d.z))
-                    .filter(|&i| block_type((state.data[i] & 0xff) as _) != BlockType::Full)
+                match dir.move_coord(&size, (d.x, d.y, d.z)) {
+                    None => d.result = CommandResult::OutOfBounds,
+                    Some(i) if block_type((state.data[i] & 0xff) as _) == BlockType::Full => {
+                        d.result = CommandResult::Blocked
+                    }
+                    v => c = v,
+                }
             }
         }
 
@@ -285,25 +538,43 @@ pub fn execute_commands(state: &mut State) {
 
     state.rev_index.sort_unstable();
 
-    for d in &mut state.drones {
+    for (i, d) in state.drones.iter_mut().enumerate() {
         let Command::BreakBlock(dir) = d.command else {
             continue;
         };
         d.command = Command::Noop;
 
-        let Some(c) = dir.move_coord(&size, (d.x, d.y, d.z)) else {
-            continue;
+        let c = match reach_coord(&state.data, dir, (d.x, d.y, d.z), d.reach) {
+            Ok(c) => c,
+            Err(r) => {
+                d.result = r;
+                continue;
+            }
         };
+        if is_frozen(&state.frozen, state.chunks_size, c) {
+            d.result = CommandResult::Blocked;
+            continue;
+        }
         let b = &mut state.data[c];
         let t = (*b & 0xff) as u8;
         if (t == 0) || !is_valid(t) {
+            d.result = CommandResult::NoTarget;
             continue;
         }
-        if block_drops(t, &mut state.rng, |src| {
-            Inventory::try_put_many(&mut d.inventory, src);
-            true
-        }) {
-            *b &= !0xff;
+        let (n, m) = state.drop_rate[t as usize];
+        if !state.rng.gen_ratio(n, m)
+            || block_drops(t, &mut state.rng, |src| {
+                pick_up(&mut state.events, &state.stack_size, i, d, src);
+                true
+            })
+        {
+            state.events.push(Event::BlockBroken {
+                x: c.0,
+                y: c.1,
+                z: c.2,
+                block: *b & !OCCUPIED_FLAG,
+            });
+            *b &= !(0xff | META_MASK);
             mark_dirty(&mut state.mesh, state.chunks_size, c);
         }
     }
@@ -314,36 +585,142 @@ pub fn execute_commands(state: &mut State) {
         };
         d.command = Command::Noop;
 
-        let Some(slot) = d.inventory.get_mut(slot as usize) else {
-            continue;
+        let c = match reach_coord(&state.data, dir, (d.x, d.y, d.z), d.reach) {
+            Ok(c) => c,
+            Err(r) => {
+                d.result = r;
+                continue;
+            }
         };
-        if slot.count == 0 {
+        if is_frozen(&state.frozen, state.chunks_size, c) {
+            d.result = CommandResult::Blocked;
             continue;
         }
-        let Some(i) = slot.item_id else {
+        let result = &mut d.result;
+        let Some(slot) = d
+            .inventory
+            .get_mut(slot as usize)
+            .filter(|_| slot < d.inventory_size)
+        else {
+            *result = CommandResult::NoItem;
             continue;
         };
-        let Some(c) = dir.move_coord(&size, (d.x, d.y, d.z)) else {
+        let (Some(i), 1..) = (slot.item_id, slot.count) else {
+            *result = CommandResult::NoItem;
             continue;
         };
         let t = state.data[c];
         if (t & 0xff) != 0 {
+            *result = CommandResult::Blocked;
             continue;
         }
 
         let Some(t) = block_place(i.into(), c, &state.data)
             .filter(|&b| ((t & OCCUPIED_FLAG) == 0) || (block_type(b) != BlockType::Full))
+            .filter(|&b| is_supported(b, c, &state.data))
         else {
+            *result = CommandResult::Blocked;
             continue;
         };
-        state.data[c] |= t as u32;
+        state.data[c] = (state.data[c] & !META_MASK) | t as u32;
         mark_dirty(&mut state.mesh, state.chunks_size, c);
+        state.events.push(Event::BlockPlaced {
+            x: c.0,
+            y: c.1,
+            z: c.2,
+            block: t as _,
+        });
         slot.count -= 1;
         if slot.count == 0 {
             slot.item_id = None;
         }
     }
 
+    for (i, d) in state.drones.iter_mut().enumerate() {
+        let Command::Harvest(dir) = d.command else {
+            continue;
+        };
+        d.command = Command::Noop;
+
+        let Some(c) = dir.move_coord(&size, (d.x, d.y, d.z)) else {
+            d.result = CommandResult::OutOfBounds;
+            continue;
+        };
+        if is_frozen(&state.frozen, state.chunks_size, c) {
+            d.result = CommandResult::Blocked;
+            continue;
+        }
+        let b = &mut state.data[c];
+        if !is_mature_crop(*b) {
+            d.result = CommandResult::NoTarget;
+            continue;
+        }
+        let (n, m) = state.drop_rate[CROP as usize];
+        if state.rng.gen_ratio(n, m) {
+            block_drops(CROP, &mut state.rng, |src| {
+                pick_up(&mut state.events, &state.stack_size, i, d, src);
+            });
+        }
+        state.events.push(Event::BlockBroken {
+            x: c.0,
+            y: c.1,
+            z: c.2,
+            block: *b & !OCCUPIED_FLAG,
+        });
+
+        // Replant
+        if let Some(slot) = d
+            .inventory_mut()
+            .iter_mut()
+            .find(|s| (s.item_id == NonZeroU16::new(CROP_SEED)) && (s.count > 0))
+        {
+            slot.count -= 1;
+            if slot.count == 0 {
+                slot.item_id = None;
+            }
+            *b = (*b & OCCUPIED_FLAG) | CROP as u32;
+        } else {
+            *b &= !(0xff | META_MASK);
+        }
+        mark_dirty(&mut state.mesh, state.chunks_size, c);
+    }
+
+    for d in &mut state.drones {
+        let Command::Look(dir) = d.command else {
+            continue;
+        };
+        d.command = Command::Noop;
+
+        if dir == Dir::Noop {
+            d.result = CommandResult::NoTarget;
+        } else {
+            d.facing = dir;
+        }
+    }
+
+    for d in &mut state.drones {
+        let Command::Paint(dir, color) = d.command else {
+            continue;
+        };
+        d.command = Command::Noop;
+
+        let Some(c) = dir.move_coord(&size, (d.x, d.y, d.z)) else {
+            d.result = CommandResult::OutOfBounds;
+            continue;
+        };
+        if is_frozen(&state.frozen, state.chunks_size, c) {
+            d.result = CommandResult::Blocked;
+            continue;
+        }
+        let b = &mut state.data[c];
+        if block_type((*b & 0xff) as u8) != BlockType::Full {
+            d.result = CommandResult::NoTarget;
+            continue;
+        }
+        *b = set_block_meta(*b, color);
+        mark_dirty(&mut state.mesh, state.chunks_size, c);
+    }
+
     for d in &mut state.drones {
         let Command::Restack = d.command else {
             continue;
@@ -355,32 +732,37 @@ pub fn execute_commands(state: &mut State) {
                 (None, None) => Ordering::Equal,
                 (None, Some(_)) => Ordering::Greater,
                 (Some(_), None) => Ordering::Less,
-                (Some(a), Some(b)) => a.cmp(b),
+                (Some(_), Some(_)) => a.key().cmp(&b.key()),
             }
         }
 
-        d.inventory.sort_unstable_by(f);
-        for i in 0..d.inventory.len() {
-            let mut dst = d.inventory[i];
+        let inventory = d.inventory_mut();
+        inventory.sort_unstable_by(f);
+        for i in 0..inventory.len() {
+            let mut dst = inventory[i];
             if dst.item_id.is_none() {
-                break;
+                continue;
             }
-            for src in &mut d.inventory[i..] {
-                if src.item_id != dst.item_id {
+            for src in &mut inventory[i + 1..] {
+                if src.item_id.is_none() {
+                    continue;
+                } else if src.key() != dst.key() {
                     break;
                 }
-                let n = src.count.min(Inventory::MAX_STACK - dst.count);
+                let m = stack_limit(&state.stack_size, dst.item_id);
+                let n = src.count.min(m.saturating_sub(dst.count));
                 dst.count += n;
                 src.count -= n;
                 if src.count == 0 {
                     src.item_id = None;
                 }
-                if dst.count == Inventory::MAX_STACK {
+                if dst.count >= m {
                     break;
                 }
             }
+            inventory[i] = dst;
         }
-        d.inventory.sort_unstable_by(f);
+        inventory.sort_unstable_by(f);
     }
 
     for i in 0..state.drones.len() {
@@ -390,25 +772,84 @@ pub fn execute_commands(state: &mut State) {
         };
         d.command = Command::Noop;
 
-        let Some(mut src) = d.inventory.get(slot as usize).copied() else {
+        let Some(mut src) = d.inventory().get(slot as usize).copied() else {
+            d.result = CommandResult::NoItem;
             continue;
         };
         if src.item_id.is_none() {
+            d.result = CommandResult::NoItem;
             continue;
         }
-        let Some(j) = dir
-            .move_coord(&size, (d.x, d.y, d.z))
-            .and_then(|c| state.rev_index.binary_search_by(|r| r.cmp_coord(&c)).ok())
+        let Some(c) = dir.move_coord(&size, (d.x, d.y, d.z)) else {
+            d.result = CommandResult::OutOfBounds;
+            continue;
+        };
+        let Some(j) = state
+            .rev_index
+            .binary_search_by(|r| r.cmp_coord(&c))
+            .ok()
             .map(|i| state.rev_index[i].i)
             .filter(|&j| i != j)
         else {
+            d.result = CommandResult::NoTarget;
             continue;
         };
+        let mut part = src.split(transfer_limit(d.transfer_rate));
         d = &mut state.drones[j];
-        Inventory::try_put_one(&mut d.inventory, &mut src);
+        Inventory::try_put_one_with(d.inventory_mut(), &mut part, |v| {
+            stack_limit(&state.stack_size, v)
+        });
+        src.unsplit(part);
         state.drones[i].inventory[slot as usize] = src;
     }
 
+    for i in 0..state.drones.len() {
+        let d = &mut state.drones[i];
+        let Command::TransferAll(dir) = d.command else {
+            continue;
+        };
+        d.command = Command::Noop;
+
+        if d.inventory().iter().all(|v| v.item_id.is_none()) {
+            d.result = CommandResult::NoItem;
+            continue;
+        }
+        let Some(c) = dir.move_coord(&size, (d.x, d.y, d.z)) else {
+            d.result = CommandResult::OutOfBounds;
+            continue;
+        };
+        let Some(j) = state
+            .rev_index
+            .binary_search_by(|r| r.cmp_coord(&c))
+            .ok()
+            .map(|i| state.rev_index[i].i)
+            .filter(|&j| i != j)
+        else {
+            d.result = CommandResult::NoTarget;
+            continue;
+        };
+
+        let mut src = d.inventory;
+        let n = d.inventory().len();
+        let mut left = transfer_limit(d.transfer_rate);
+        let d = &mut state.drones[j];
+        for s in &mut src[..n] {
+            if left == 0 {
+                break;
+            } else if s.item_id.is_none() {
+                continue;
+            }
+            let mut part = s.split(left);
+            let count = part.count;
+            Inventory::try_put_one_with(d.inventory_mut(), &mut part, |v| {
+                stack_limit(&state.stack_size, v)
+            });
+            left -= count - part.count;
+            s.unsplit(part);
+        }
+        state.drones[i].inventory = src;
+    }
+
     for i in 0..state.drones.len() {
         let mut d = &mut state.drones[i];
         let Command::RecvItem(dir, slot) = d.command else {
@@ -416,36 +857,48 @@ pub fn execute_commands(state: &mut State) {
         };
         d.command = Command::Noop;
 
-        let Some(mut dst) = d.inventory.get(slot as usize).copied() else {
+        let Some(mut dst) = d.inventory().get(slot as usize).copied() else {
+            d.result = CommandResult::NoItem;
             continue;
         };
-        let Some(j) = dir
-            .move_coord(&size, (d.x, d.y, d.z))
-            .and_then(|c| state.rev_index.binary_search_by(|r| r.cmp_coord(&c)).ok())
+        let mut left = transfer_limit(d.transfer_rate);
+        let Some(c) = dir.move_coord(&size, (d.x, d.y, d.z)) else {
+            d.result = CommandResult::OutOfBounds;
+            continue;
+        };
+        let Some(j) = state
+            .rev_index
+            .binary_search_by(|r| r.cmp_coord(&c))
+            .ok()
             .map(|i| state.rev_index[i].i)
             .filter(|&j| i != j)
         else {
+            d.result = CommandResult::NoTarget;
             continue;
         };
         d = &mut state.drones[j];
 
-        for src in &mut d.inventory {
+        for src in d.inventory_mut() {
             match (src.item_id, dst.item_id) {
                 (None, _) => (),
                 (_, None) => {
-                    (dst.item_id, dst.count) = (src.item_id, src.count);
-                    (src.item_id, src.count) = (None, 0);
+                    dst = src.split(left.min(stack_limit(&state.stack_size, src.item_id)));
+                    if src.count == 0 {
+                        src.item_id = None;
+                    }
                     break;
                 }
-                (a, b) if a != b => (),
+                _ if src.key() != dst.key() => (),
                 _ => {
-                    let n = src.count.min(Inventory::MAX_STACK - dst.count);
+                    let m = stack_limit(&state.stack_size, dst.item_id);
+                    let n = src.count.min(m.saturating_sub(dst.count)).min(left);
                     dst.count += n;
                     src.count -= n;
+                    left -= n;
                     if src.count == 0 {
                         src.item_id = None;
                     }
-                    if dst.count == Inventory::MAX_STACK {
+                    if (dst.count >= m) || (left == 0) {
                         break;
                     }
                 }
@@ -460,6 +913,143 @@ pub fn execute_commands(state: &mut State) {
     }
 }
 
+/// Cell reach steps away from coord, with clear line of sight to it.
+///
+/// Any full block in between blocks the target.
+fn reach_coord(
+    data: &Array3<u32>,
+    dir: Dir,
+    mut coord: (usize, usize, usize),
+    reach: u8,
+) -> Result<(usize, usize, usize), CommandResult> {
+    let size = data.raw_dim().into_pattern();
+    for i in 0..reach.max(1) {
+        if i > 0 && block_type((data[coord] & 0xff) as _) == BlockType::Full {
+            return Err(CommandResult::Blocked);
+        }
+        coord = dir
+            .move_coord(&size, coord)
+            .ok_or(CommandResult::OutOfBounds)?;
+    }
+    Ok(coord)
+}
+
+/// Items a transfer command can move in a tick.
+const fn transfer_limit(rate: u8) -> u8 {
+    if rate == 0 {
+        u8::MAX
+    } else {
+        rate
+    }
+}
+
+/// Stack size of item, falling back to [`Inventory::MAX_STACK`].
+pub fn stack_limit(stack_size: &HashMap<u16, u8>, item: Option<NonZeroU16>) -> u8 {
+    item.and_then(|v| stack_size.get(&v.get()).copied())
+        .unwrap_or(Inventory::MAX_STACK)
+}
+
+/// Put items into drone inventory, recording what is picked up.
+fn pick_up(
+    events: &mut Vec<Event>,
+    stack_size: &HashMap<u16, u8>,
+    i: usize,
+    d: &mut Drone,
+    src: &mut [Inventory],
+) {
+    let prev = src.to_vec();
+
+    let max = |v| stack_limit(stack_size, v);
+    match d.drop_policy {
+        DropPolicy::Stack => {
+            Inventory::try_put_many_with(d.inventory_mut(), src, max);
+        }
+        DropPolicy::Ordered => {
+            for s in &mut *src {
+                Inventory::try_put_from(d.inventory_mut(), s, 0, max);
+            }
+        }
+        DropPolicy::RoundRobin => {
+            for s in &mut *src {
+                let start = d.drop_cursor as usize;
+                if let Some(j) = Inventory::try_put_from(d.inventory_mut(), s, start, max) {
+                    d.drop_cursor = ((j + 1) % d.inventory().len()) as _;
+                }
+            }
+        }
+    }
+    for (a, b) in prev.iter().zip(&*src) {
+        let Some(item) = a.item_id else {
+            continue;
+        };
+        let count = a.count - if b.item_id == a.item_id { b.count } else { 0 };
+        if count > 0 {
+            events.push(Event::ItemPickedUp {
+                drone: i,
+                item: item.get(),
+                count,
+            });
+        }
+    }
+}
+
+#[inline]
+fn swap_drone(state: &mut State, size: &(usize, usize, usize)) {
+    for (i, (d, r)) in state.drones.iter().zip(&mut state.rev_index).enumerate() {
+        *r = MoveIndex {
+            x: d.x,
+            y: d.y,
+            z: d.z,
+            i,
+        };
+    }
+    state.rev_index.sort_unstable();
+
+    for i in 0..state.drones.len() {
+        let d = &mut state.drones[i];
+        let Command::Swap(dir) = d.command else {
+            continue;
+        };
+        d.command = Command::Noop;
+
+        let Some(c) = dir.move_coord(size, (d.x, d.y, d.z)) else {
+            d.result = CommandResult::OutOfBounds;
+            continue;
+        };
+        let Some(r) = state
+            .rev_index
+            .binary_search_by(|r| r.cmp_coord(&c))
+            .ok()
+            .map(|i| state.rev_index[i])
+            .filter(|r| r.i != i)
+        else {
+            d.result = CommandResult::NoTarget;
+            continue;
+        };
+        let (a, b) = (&state.drones[i], &state.drones[r.i]);
+        let blocked = match b.command {
+            // Target has already been swapped this tick
+            _ if (b.x, b.y, b.z) != (r.x, r.y, r.z) => true,
+            Command::Move(_) => true,
+            Command::Swap(dir) => dir.move_coord(size, (r.x, r.y, r.z)) != Some((a.x, a.y, a.z)),
+            _ => false,
+        };
+        if blocked {
+            state.drones[i].result = CommandResult::Blocked;
+            continue;
+        }
+
+        let c = (a.x, a.y, a.z);
+        let d = &mut state.drones[r.i];
+        if let Command::Swap(_) = d.command {
+            d.command = Command::Noop;
+        }
+        (d.x, d.y, d.z) = c;
+        let d = &mut state.drones[i];
+        (d.x, d.y, d.z) = (r.x, r.y, r.z);
+    }
+}
+
 #[inline]
 fn move_drone(state: &mut State) {
     state.move_index.sort_unstable_by(|a, b| {
@@ -485,6 +1075,7 @@ fn move_drone(state: &mut State) {
         let d = &mut state.drones[b.i];
         if matches!(d.command, Command::Move(_)) {
             d.command = Command::Noop;
+            d.result = CommandResult::Blocked;
         }
     }
 
@@ -508,6 +1099,7 @@ fn move_drone(state: &mut State) {
                 continue;
             }
             d.command = Command::Noop;
+            d.result = CommandResult::Blocked;
             f(state, j);
         }
     }
@@ -525,10 +1117,14 @@ fn move_drone(state: &mut State) {
     }
     for m in &state.move_index {
         let d = &mut state.drones[m.i];
-        if matches!(d.command, Command::Move(_)) {
+        if let Command::Move(dir) = d.command {
             state.data[(m.x, m.y, m.z)] |= OCCUPIED_FLAG;
             (d.x, d.y, d.z) = (m.x, m.y, m.z);
             d.command = Command::Noop;
+            // Turn toward horizontal movement
+            if !matches!(dir, Dir::Up | Dir::Down) {
+                d.facing = dir;
+            }
         }
     }
 }
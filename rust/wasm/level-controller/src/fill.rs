@@ -0,0 +1,101 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::{HashSet, VecDeque};
+
+use ndarray::{Array3, Dimension};
+
+use super::drone::Dir;
+
+const DIRS: [Dir; 6] = [
+    Dir::Up,
+    Dir::Down,
+    Dir::Left,
+    Dir::Right,
+    Dir::Front,
+    Dir::Back,
+];
+
+/// Breadth-first flood fill from start.
+///
+/// Visits face-connected cells whose value satisfies predicate,
+/// stopping after max_cells cells. Start cell must satisfy predicate too.
+pub fn flood_fill<F>(
+    data: &Array3<u32>,
+    start: (usize, usize, usize),
+    mut predicate: F,
+    max_cells: usize,
+) -> Vec<(usize, usize, usize)>
+where
+    F: FnMut(u32) -> bool,
+{
+    let mut ret = Vec::new();
+    if max_cells == 0 || !data.get(start).is_some_and(|&b| predicate(b)) {
+        return ret;
+    }
+
+    let size = data.raw_dim().into_pattern();
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(c) = queue.pop_front() {
+        ret.push(c);
+        if ret.len() >= max_cells {
+            break;
+        }
+
+        for d in DIRS {
+            let Some(c) = d.move_coord(&size, c) else {
+                continue;
+            };
+            if !visited.insert(c) || !predicate(data[c]) {
+                continue;
+            }
+            queue.push_back(c);
+        }
+    }
+
+    ret
+}
+
+/// Breadth-first step count from target over cells satisfying predicate.
+///
+/// Steps are capped at max. Cells that are unreachable, or do not satisfy
+/// predicate, are set to max.
+pub fn distance_field<F>(
+    data: &Array3<u32>,
+    target: (usize, usize, usize),
+    mut predicate: F,
+    max: u8,
+) -> Array3<u8>
+where
+    F: FnMut(u32) -> bool,
+{
+    let mut ret = Array3::from_elem(data.raw_dim(), max);
+    if max == 0 || !data.get(target).is_some_and(|&b| predicate(b)) {
+        return ret;
+    }
+
+    let size = data.raw_dim().into_pattern();
+    ret[target] = 0;
+    let mut queue = VecDeque::from([target]);
+    while let Some(c) = queue.pop_front() {
+        let v = ret[c] + 1;
+        if v >= max {
+            continue;
+        }
+
+        for d in DIRS {
+            let Some(c) = d.move_coord(&size, c) else {
+                continue;
+            };
+            if ret[c] <= v || !predicate(data[c]) {
+                continue;
+            }
+            ret[c] = v;
+            queue.push_back(c);
+        }
+    }
+
+    ret
+}
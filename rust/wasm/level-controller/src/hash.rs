@@ -0,0 +1,55 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::hash::{Hash, Hasher};
+
+use ndarray::Dimension;
+
+use super::{State, OCCUPIED_FLAG};
+
+/// FNV-1a hasher. Unlike the std default, it is not randomly keyed.
+#[derive(Debug, Clone, Copy)]
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+pub fn state_hash(state: &State) -> u64 {
+    let mut h = FnvHasher::default();
+
+    let (x, y, z) = state.data.raw_dim().into_pattern();
+    h.write_u64(x as _);
+    h.write_u64(y as _);
+    h.write_u64(z as _);
+    for &b in &state.data {
+        // Occupancy is derived from drones
+        h.write_u32(b & !OCCUPIED_FLAG);
+    }
+
+    // Drones are summed so their order does not matter
+    let d = state.drones.iter().fold(0u64, |a, d| {
+        let mut h = FnvHasher::default();
+        d.hash(&mut h);
+        a.wrapping_add(h.finish())
+    });
+    h.write_u64(d);
+
+    h.finish()
+}
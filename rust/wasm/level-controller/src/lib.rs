@@ -5,27 +5,44 @@
 
 mod blocks;
 mod drone;
+mod fill;
+mod hash;
 mod meshgen;
 mod pubsub;
+mod ray;
+mod rngstate;
 #[cfg(test)]
 mod tests;
 
+use std::collections::HashMap;
 use std::ptr;
 use std::rc::Rc;
 
 use glam::f32::*;
 use ndarray::{s, Array, Array3, Dimension};
 use rand::{Rng, SeedableRng};
-use rand_xoshiro::Xoshiro512StarStar;
+use rand_xoshiro::{Seed512, Xoshiro512StarStar};
 
 #[derive(Debug, Default)]
 struct Mesh {
     dirty: bool,
+    updated: bool,
     vertex: Vec<Vec3>,
     normal: Vec<Vec3>,
     tangent: Vec<Vec4>,
     uv: Vec<Vec2>,
+    color: Vec<Vec4>,
     index: Vec<u32>,
+    ranges: Vec<MeshRange>,
+
+    old_vertex: Vec<Vec3>,
+    old_normal: Vec<Vec3>,
+    old_tangent: Vec<Vec4>,
+    old_uv: Vec<Vec2>,
+    old_color: Vec<Vec4>,
+    old_index: Vec<u32>,
+    vertex_changed: [usize; 2],
+    index_changed: [usize; 2],
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -42,6 +59,29 @@ pub struct ExportMesh {
     pub tangent: *const Vec4,
     pub uv: *const Vec2,
     pub index: *const u32,
+    pub color: *const Vec4,
+    pub range_count: usize,
+    pub ranges: *const MeshRange,
+}
+
+/// Exposed face of a full block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Face {
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+    pub dir: drone::Dir,
+    pub block: u8,
+}
+
+/// Index range of a single block type, if mesh is split.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct MeshRange {
+    pub block: u32,
+    pub start: u32,
+    pub end: u32,
 }
 
 impl ExportMesh {
@@ -58,27 +98,112 @@ impl ExportMesh {
             tangent: ptr::null(),
             uv: ptr::null(),
             index: ptr::null(),
+            color: ptr::null(),
+            range_count: 0,
+            ranges: ptr::null(),
         }
     }
 }
 
+/// Changed ranges (start, end) of last mesh generation.
+///
+/// Ranges index the new buffers, so items past it are unchanged
+/// unless the buffer length also changed.
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct ExportMeshDiff {
+    pub vertex_start: usize,
+    pub vertex_end: usize,
+    pub index_start: usize,
+    pub index_end: usize,
+}
+
+/// Notable things happened during step, for host effects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum Event {
+    BlockBroken {
+        x: usize,
+        y: usize,
+        z: usize,
+        block: u32,
+    },
+    BlockPlaced {
+        x: usize,
+        y: usize,
+        z: usize,
+        block: u32,
+    },
+    ItemPickedUp {
+        drone: usize,
+        item: u16,
+        count: u8,
+    },
+}
+
+/// Order of drone commands and environment (random tick) within a tick.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TickOrder {
+    #[default]
+    CommandFirst,
+    EnvironmentFirst,
+    /// Commands run between two halves of random tick. Growth runs last.
+    Interleave,
+}
+
 const OCCUPIED_FLAG: u32 = 0x8000_0000;
+const META_SHIFT: u32 = 8;
+const META_MASK: u32 = 0xf << META_SHIFT;
+/// Cells walked by ray placement before giving up.
+const MAX_RAY_STEPS: usize = 256;
 
 struct State {
     rng: Xoshiro512StarStar,
     tick_count: usize,
+    tick_order: TickOrder,
+    paused: bool,
+    /// Ticks advanced since start.
+    elapsed_ticks: u64,
     data: Array3<u32>,
 
     chunks_size: usize,
     mesh: Array3<Mesh>,
     export_mesh: Array3<ExportMesh>,
+    export_mesh_diff: Array3<ExportMeshDiff>,
+    lod_mesh: Mesh,
+    export_lod_mesh: ExportMesh,
+    tickable: Array3<bool>,
+    /// Chunks skipped by random tick and drone edits.
+    frozen: Array3<bool>,
+    tick_chunks: Vec<(usize, usize, usize)>,
+    /// Random tick distinct cells of each chunk.
+    tick_shuffle: bool,
 
     drones: Vec<drone::Drone>,
+    events: Vec<Event>,
+    export_events: Vec<Event>,
     pubsub: pubsub::PubSub,
 
     move_index: Vec<drone::MoveIndex>,
     rev_index: Vec<drone::MoveIndex>,
     key_cache: Vec<u8>,
+    rng_cache: [u8; 64],
+    block_cache: Vec<[u32; 4]>,
+    inventory_cache: Vec<drone::Inventory>,
+    ray_cache: [usize; 3],
+    distance_cache: Array3<u8>,
+    face_cache: Vec<Face>,
+
+    drop_rate: [(u32, u32); 256],
+    stack_size: HashMap<u16, u8>,
+    mesh_boundary: bool,
+    mesh_split: bool,
+    mesh_reverse_winding: bool,
+    /// Keep previous mesh data to report changed ranges.
+    mesh_diff: bool,
+    growth_ticks: [u32; 256],
+    growth_timer: HashMap<(usize, usize, usize), u32>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -134,22 +259,440 @@ impl State {
         Self {
             rng: Xoshiro512StarStar::seed_from_u64(seed),
             tick_count,
+            tick_order: TickOrder::default(),
+            paused: false,
+            elapsed_ticks: 0,
             data,
             chunks_size,
             mesh,
             export_mesh,
+            export_mesh_diff: Array::default(shape),
+            lod_mesh: Mesh::default(),
+            export_lod_mesh: ExportMesh::new(),
+            tickable: Array::from_elem(shape, false),
+            frozen: Array::from_elem(shape, false),
+            tick_chunks: Vec::new(),
+            tick_shuffle: false,
             drones,
+            events: Vec::new(),
+            export_events: Vec::new(),
             pubsub,
             move_index: vec![drone::MoveIndex::default(); drone_count],
             rev_index: vec![drone::MoveIndex::default(); drone_count],
             key_cache: Vec::new(),
+            rng_cache: [0; 64],
+            block_cache: Vec::new(),
+            inventory_cache: Vec::new(),
+            ray_cache: [0; 3],
+            distance_cache: Array3::default((0, 0, 0)),
+            face_cache: Vec::new(),
+            drop_rate: [(1, 1); 256],
+            stack_size: HashMap::new(),
+            mesh_boundary: true,
+            mesh_split: false,
+            mesh_reverse_winding: false,
+            mesh_diff: false,
+            growth_ticks: [0; 256],
+            growth_timer: HashMap::new(),
+        }
+    }
+
+    /// Override stack size of item. Zero resets to default.
+    fn set_stack_size(&mut self, item: u16, size: u8) {
+        if size == 0 {
+            self.stack_size.remove(&item);
+        } else {
+            self.stack_size.insert(item, size);
+        }
+    }
+
+    fn set_drop_rate(&mut self, block: u8, numerator: u32, denominator: u32) {
+        if denominator == 0 {
+            return;
+        }
+        self.drop_rate[block as usize] = (numerator.min(denominator), denominator);
+    }
+
+    /// Set many blocks at once, then mark and refresh affected chunks.
+    ///
+    /// Drone occupancy is kept. Out of bounds coordinates are ignored.
+    fn set_blocks(&mut self, blocks: impl IntoIterator<Item = ((usize, usize, usize), u32)>) {
+        for (c, b) in blocks {
+            let Some(v) = self.data.get_mut(c) else {
+                continue;
+            };
+            *v = (*v & OCCUPIED_FLAG) | (b & !OCCUPIED_FLAG);
+
+            let (x, y, z) = c;
+            let s = self.chunks_size;
+            self.mesh[(x / s, y / s, z / s)].dirty = true;
+        }
+
+        self.update_tickable();
+    }
+
+    /// Save RNG state, so that import_rng() resumes the exact same stream.
+    ///
+    /// State words are read through serde, as they are private.
+    fn export_rng(&self) -> [u8; 64] {
+        let words: [u64; 8] =
+            rngstate::state_words(&self.rng).expect("RNG state should be 8 words");
+        let mut ret = [0; 64];
+        for (d, s) in ret.chunks_exact_mut(8).zip(words) {
+            d.copy_from_slice(&s.to_le_bytes());
+        }
+        ret
+    }
+
+    fn import_rng(&mut self, seed: [u8; 64]) {
+        self.rng = Xoshiro512StarStar::from_seed(Seed512(seed));
+    }
+
+    /// Place block on the air side of the first full block hit by ray.
+    ///
+    /// Fails if block is air or invalid, or if target cell is out of level,
+    /// has a block or a drone in it.
+    fn place_block_ray(
+        &mut self,
+        origin: Vec3,
+        dir: Vec3,
+        block: u8,
+    ) -> Option<(usize, usize, usize)> {
+        if (block == 0) || !blocks::is_valid(block) {
+            return None;
+        }
+        let (_, c) = ray::raycast(
+            &self.data,
+            origin,
+            dir,
+            |b| blocks::block_type((b & 0xff) as u8) == blocks::BlockType::Full,
+            MAX_RAY_STEPS,
+        )?;
+        let c = c?;
+        if (self.data[c] != 0)
+            || drone::is_frozen(&self.frozen, self.chunks_size, c)
+            || !blocks::is_supported(block, c, &self.data)
+        {
+            return None;
+        }
+
+        self.data[c] = block as u32;
+        drone::mark_dirty(&mut self.mesh, self.chunks_size, c);
+        self.events.push(Event::BlockPlaced {
+            x: c.0,
+            y: c.1,
+            z: c.2,
+            block: block as _,
+        });
+        Some(c)
+    }
+
+    /// Whether every block in [start, end) is air. Region is clipped to level.
+    fn is_region_air(&self, [x0, y0, z0]: [usize; 3], [x1, y1, z1]: [usize; 3]) -> bool {
+        let (sx, sy, sz) = self.data.raw_dim().into_pattern();
+        let (x1, y1, z1) = (x1.min(sx), y1.min(sy), z1.min(sz));
+        if (x0 >= x1) || (y0 >= y1) || (z0 >= z1) {
+            return true;
+        }
+
+        self.data
+            .slice(s![x0..x1, y0..y1, z0..z1])
+            .iter()
+            .all(|&b| (b & 0xff) == 0)
+    }
+
+    /// Number of drones with a command given or still waiting.
+    fn pending_command_count(&self) -> usize {
+        self.drones
+            .iter()
+            .filter(|d| !matches!(d.command, drone::Command::Noop) || d.wait > 0)
+            .count()
+    }
+
+    /// Reset all drone commands to noop, including waits.
+    fn clear_all_commands(&mut self) {
+        for d in &mut self.drones {
+            d.command = drone::Command::Noop;
+            d.wait = 0;
+        }
+    }
+
+    /// Copy inventory of drone i into inventory cache.
+    fn export_inventory(&mut self, i: usize) -> Option<&[drone::Inventory]> {
+        let d = self.drones.get(i)?;
+        self.inventory_cache.clear();
+        self.inventory_cache.extend_from_slice(d.inventory());
+        Some(&self.inventory_cache)
+    }
+
+    /// Compute step counts from target over non-full cells into distance cache.
+    ///
+    /// Field has the same size and layout as level data, capped at max.
+    fn query_distance_field(&mut self, target: (usize, usize, usize), max: u8) -> &Array3<u8> {
+        self.distance_cache = fill::distance_field(
+            &self.data,
+            target,
+            |b| blocks::block_type((b & 0xff) as _) != blocks::BlockType::Full,
+            max,
+        );
+        &self.distance_cache
+    }
+
+    /// Make block grow to maturity in exactly the given ticks,
+    /// instead of on random ticks. Zero restores random growth.
+    ///
+    /// Only crops have growth stages, other blocks are ignored.
+    fn set_growth_ticks(&mut self, block: u8, ticks: u32) {
+        if block == blocks::CROP {
+            self.growth_ticks[block as usize] = ticks;
+        }
+    }
+
+    /// Advance deterministic growth timers of blocks in tickable chunks.
+    fn growth_tick(&mut self) {
+        if self.growth_ticks.iter().all(|&v| v == 0) {
+            self.growth_timer.clear();
+            return;
+        }
+
+        let size = self.chunks_size;
+        let (ex, ey, ez) = self.data.raw_dim().into_pattern();
+        for &(x, y, z) in &self.tick_chunks {
+            let mut data = self.data.slice_mut(s![
+                x * size..((x + 1) * size).min(ex),
+                y * size..((y + 1) * size).min(ey),
+                z * size..((z + 1) * size).min(ez),
+            ]);
+            let ticks = self.growth_ticks[blocks::CROP as usize];
+            for ((i, j, k), b) in data.indexed_iter_mut() {
+                if (ticks == 0) || ((*b & 0xff) as u8 != blocks::CROP) {
+                    continue;
+                }
+                let c = (x * size + i, y * size + j, z * size + k);
+                let timer = self.growth_timer.remove(&c);
+                let meta = blocks::block_meta(*b) as u32;
+
+                // Restart timer at current stage if it no longer matches
+                // (eg. replanted, or grown before timers were enabled)
+                let mature = blocks::CROP_MATURE as u32;
+                let t = match timer {
+                    Some(t) if (t * mature / ticks).min(mature) == meta => t,
+                    _ => (meta * ticks).div_ceil(mature),
+                } + 1;
+                let stage = (t * mature / ticks).min(mature);
+                *b = blocks::set_block_meta(*b, stage as u8);
+                if stage < mature {
+                    self.growth_timer.insert(c, t);
+                }
+            }
+        }
+
+        // Drop timers of cells no longer holding a crop
+        let data = &self.data;
+        self.growth_timer
+            .retain(|&c, _| (data[c] & 0xff) as u8 == blocks::CROP);
+    }
+
+    /// Refresh tickable flag of all dirty chunks.
+    fn update_tickable(&mut self) {
+        let data = self.data.view();
+        let size = self.chunks_size;
+        let (ex, ey, ez) = data.raw_dim().into_pattern();
+        for (((x, y, z), t), m) in self.tickable.indexed_iter_mut().zip(&self.mesh) {
+            if !m.dirty {
+                continue;
+            }
+            *t = data
+                .slice(s![
+                    x * size..((x + 1) * size).min(ex),
+                    y * size..((y + 1) * size).min(ey),
+                    z * size..((z + 1) * size).min(ez),
+                ])
+                .iter()
+                .any(|&b| blocks::is_tickable((b & 0xff) as _));
         }
     }
 
+    /// Advance one tick, unless paused.
+    fn tick(&mut self) {
+        if !self.paused {
+            self.step_once();
+        }
+    }
+
+    /// Run one simulation tick, in the configured order, ignoring pause.
+    fn step_once(&mut self) {
+        match self.tick_order {
+            TickOrder::CommandFirst => {
+                drone::execute_commands(self);
+                self.random_tick();
+            }
+            TickOrder::EnvironmentFirst => {
+                self.random_tick();
+                drone::execute_commands(self);
+            }
+            TickOrder::Interleave => {
+                let n = self.tick_count / 2;
+                self.sample_random_tick(n);
+                drone::execute_commands(self);
+                self.sample_random_tick(self.tick_count - n);
+                self.growth_tick();
+            }
+        }
+        self.elapsed_ticks += 1;
+    }
+
+    fn random_tick(&mut self) {
+        self.sample_random_tick(self.tick_count);
+        self.growth_tick();
+    }
+
+    /// Randomly tick count blocks of tickable chunks.
+    fn sample_random_tick(&mut self, count: usize) {
+        self.update_tickable();
+
+        self.tick_chunks.clear();
+        self.tick_chunks.extend(
+            self.tickable
+                .indexed_iter()
+                .zip(&self.frozen)
+                .filter_map(|((i, &t), &f)| if t && !f { Some(i) } else { None }),
+        );
+
+        let size = self.data.raw_dim().into_pattern();
+        let (chunks, chunks_size) = (&*self.tick_chunks, self.chunks_size);
+        let mut cells = if self.tick_shuffle {
+            sample_tick_distinct(&mut self.rng, chunks, chunks_size, size, count)
+        } else {
+            Vec::new()
+        }
+        .into_iter();
+        let shuffle = self.tick_shuffle;
+        let mut n = 0;
+        blocks::random_tick(
+            &mut self.rng,
+            |r| {
+                if shuffle {
+                    return cells.next();
+                } else if n >= count {
+                    return None;
+                }
+                n += 1;
+                sample_tick(r, chunks, chunks_size, size)
+            },
+            |t| (t == blocks::CROP) && (self.growth_ticks[t as usize] != 0),
+            &mut self.data,
+        );
+    }
+
+    /// Regenerate at most max_chunks dirty chunk meshes.
+    ///
+    /// Returns how many chunks are still dirty.
+    fn update_mesh(&mut self, mut max_chunks: usize) -> usize {
+        // Dirty flag is cleared below, refresh tickable flags first
+        self.update_tickable();
+
+        let data = self.data.view();
+        let mut remaining = 0;
+        for ((x, y, z), mesh) in self.mesh.indexed_iter_mut() {
+            if !mesh.dirty {
+                continue;
+            } else if max_chunks == 0 {
+                remaining += 1;
+                continue;
+            }
+            max_chunks -= 1;
+            if self.mesh_diff {
+                meshgen::keep_old_mesh(mesh);
+            }
+            meshgen::gen_mesh(
+                data,
+                self.chunks_size,
+                [
+                    x * self.chunks_size,
+                    y * self.chunks_size,
+                    z * self.chunks_size,
+                ],
+                self.mesh_boundary,
+                self.mesh_split,
+                self.mesh_reverse_winding,
+                mesh,
+            );
+            meshgen::diff_mesh(mesh);
+            mesh.dirty = false;
+            mesh.updated = true;
+        }
+
+        remaining
+    }
+
+    /// List exposed faces of a single chunk into face cache.
+    fn export_face_list(&mut self, [x, y, z]: [usize; 3]) -> Option<&[Face]> {
+        let (x_, y_, z_) = self.mesh.raw_dim().into_pattern();
+        if (x >= x_) || (y >= y_) || (z >= z_) {
+            return None;
+        }
+
+        meshgen::gen_face_list(
+            self.data.view(),
+            self.chunks_size,
+            [
+                x * self.chunks_size,
+                y * self.chunks_size,
+                z * self.chunks_size,
+            ],
+            self.mesh_boundary,
+            &mut self.face_cache,
+        );
+        Some(&self.face_cache)
+    }
+
+    /// Generate coarse mesh of a single chunk.
+    fn update_lod_mesh(&mut self, [x, y, z]: [usize; 3], level: u32) -> Option<&ExportMesh> {
+        let (x_, y_, z_) = self.mesh.raw_dim().into_pattern();
+        if (x >= x_) || (y >= y_) || (z >= z_) || (level >= usize::BITS) {
+            return None;
+        }
+
+        let m = &mut self.lod_mesh;
+        let start = [
+            x * self.chunks_size,
+            y * self.chunks_size,
+            z * self.chunks_size,
+        ];
+        meshgen::gen_mesh_lod(
+            self.data.view(),
+            self.chunks_size,
+            start,
+            level,
+            self.mesh_reverse_winding,
+            m,
+        );
+        self.export_lod_mesh = ExportMesh {
+            x: start[0],
+            y: start[1],
+            z: start[2],
+            dirty: true,
+            vertex_count: m.vertex.len(),
+            index_count: m.index.len(),
+
+            vertex: m.vertex.as_ptr(),
+            normal: m.normal.as_ptr(),
+            tangent: m.tangent.as_ptr(),
+            uv: m.uv.as_ptr(),
+            index: m.index.as_ptr(),
+            color: m.color.as_ptr(),
+            range_count: m.ranges.len(),
+            ranges: m.ranges.as_ptr(),
+        };
+
+        Some(&self.export_lod_mesh)
+    }
+
     fn write_export(&mut self, export: &mut ExportState, clear_dirty: bool) {
         self.export_mesh.zip_mut_with(&self.mesh, |o, i| {
             *o = ExportMesh {
-                dirty: i.dirty,
+                dirty: i.updated,
                 vertex_count: i.vertex.len(),
                 index_count: i.index.len(),
 
@@ -158,13 +701,28 @@ impl State {
                 tangent: i.tangent.as_ptr(),
                 uv: i.uv.as_ptr(),
                 index: i.index.as_ptr(),
+                color: i.color.as_ptr(),
+                range_count: i.ranges.len(),
+                ranges: i.ranges.as_ptr(),
 
                 ..*o
             }
         });
+        self.export_mesh_diff.zip_mut_with(&self.mesh, |o, i| {
+            *o = if i.updated {
+                ExportMeshDiff {
+                    vertex_start: i.vertex_changed[0],
+                    vertex_end: i.vertex_changed[1],
+                    index_start: i.index_changed[0],
+                    index_end: i.index_changed[1],
+                }
+            } else {
+                ExportMeshDiff::default()
+            };
+        });
         if clear_dirty {
             for m in &mut self.mesh {
-                m.dirty = false;
+                m.updated = false;
             }
         }
 
@@ -185,6 +743,74 @@ impl State {
     }
 }
 
+/// Pick a random cell inside one of the given chunks.
+fn sample_tick<R: Rng>(
+    r: &mut R,
+    chunks: &[(usize, usize, usize)],
+    chunks_size: usize,
+    (sx, sy, sz): (usize, usize, usize),
+) -> Option<(usize, usize, usize)> {
+    if chunks.is_empty() {
+        return None;
+    }
+    let (x, y, z) = chunks[r.gen_range(0..chunks.len())];
+    let (x, y, z) = (x * chunks_size, y * chunks_size, z * chunks_size);
+    Some((
+        r.gen_range(x..(x + chunks_size).min(sx)),
+        r.gen_range(y..(y + chunks_size).min(sy)),
+        r.gen_range(z..(z + chunks_size).min(sz)),
+    ))
+}
+
+/// Sample count cells of random chunks, never repeating a cell.
+///
+/// Each chunk shuffles its cell indices and takes the first ones.
+fn sample_tick_distinct<R: Rng>(
+    r: &mut R,
+    chunks: &[(usize, usize, usize)],
+    chunks_size: usize,
+    (sx, sy, sz): (usize, usize, usize),
+    count: usize,
+) -> Vec<(usize, usize, usize)> {
+    let mut ret = Vec::new();
+    if chunks.is_empty() {
+        return ret;
+    }
+    let mut counts = vec![0usize; chunks.len()];
+    for _ in 0..count {
+        counts[r.gen_range(0..chunks.len())] += 1;
+    }
+
+    let mut index = Vec::new();
+    for (&(x, y, z), n) in chunks.iter().zip(counts) {
+        if n == 0 {
+            continue;
+        }
+        let (x, y, z) = (x * chunks_size, y * chunks_size, z * chunks_size);
+        let (wx, wy, wz) = (
+            (x + chunks_size).min(sx) - x,
+            (y + chunks_size).min(sy) - y,
+            (z + chunks_size).min(sz) - z,
+        );
+        index.clear();
+        index.extend(0..wx * wy * wz);
+
+        // Partial Fisher-Yates shuffle
+        let n = n.min(index.len());
+        for i in 0..n {
+            let j = r.gen_range(i..index.len());
+            index.swap(i, j);
+        }
+        ret.extend(
+            index[..n]
+                .iter()
+                .map(|&i| (x + i / (wy * wz), y + i / wz % wy, z + i % wz)),
+        );
+    }
+
+    ret
+}
+
 impl ExportState {
     const fn new() -> Self {
         Self {
@@ -238,65 +864,115 @@ const _: () = {
     pub extern "C" fn generate_mesh() {
         let state = unsafe { STATE.as_mut().unwrap() };
 
-        let data = state.data.view();
-        for ((x, y, z), mesh) in state.mesh.indexed_iter_mut() {
-            if !mesh.dirty {
-                continue;
-            }
-            meshgen::gen_mesh(
-                data,
-                state.chunks_size,
-                [
-                    x * state.chunks_size,
-                    y * state.chunks_size,
-                    z * state.chunks_size,
-                ],
-                mesh,
-            );
-        }
+        state.update_tickable();
+        state.update_mesh(usize::MAX);
+
+        write_export(state, true);
+    }
+
+    /// Like generate_mesh(), but only regenerate up to max_chunks chunks.
+    ///
+    /// Returns number of chunks still dirty.
+    #[no_mangle]
+    pub extern "C" fn generate_mesh_budgeted(max_chunks: usize) -> usize {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.update_tickable();
+        let ret = state.update_mesh(max_chunks);
 
         write_export(state, true);
+        ret
     }
 
     #[no_mangle]
     pub extern "C" fn step() {
         let state = unsafe { STATE.as_mut().unwrap() };
 
-        drone::execute_commands(state);
+        state.tick();
 
-        let (sx, sy, sz) = state.data.raw_dim().into_pattern();
-        let mut n = 0;
-        blocks::random_tick(
-            &mut state.rng,
-            |r| {
-                if n >= state.tick_count {
-                    return None;
-                }
-                n += 1;
-                Some((r.gen_range(0..sx), r.gen_range(0..sy), r.gen_range(0..sz)))
-            },
-            &mut state.data,
-        );
+        state.update_mesh(usize::MAX);
+        write_export(state, true);
+    }
 
-        let data = state.data.view();
-        for ((x, y, z), mesh) in state.mesh.indexed_iter_mut() {
-            if !mesh.dirty {
-                continue;
-            }
-            meshgen::gen_mesh(
-                data,
-                state.chunks_size,
-                [
-                    x * state.chunks_size,
-                    y * state.chunks_size,
-                    z * state.chunks_size,
-                ],
-                mesh,
-            );
-        }
+    /// Like step(), but advances even if paused.
+    #[no_mangle]
+    pub extern "C" fn step_once() {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.step_once();
+
+        state.update_mesh(usize::MAX);
         write_export(state, true);
     }
 
+    /// Pause ticking in step(). Export still happens.
+    #[no_mangle]
+    pub extern "C" fn set_paused(paused: bool) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.paused = paused;
+    }
+
+    #[no_mangle]
+    pub extern "C" fn is_paused() -> bool {
+        let state = unsafe { STATE.as_ref().unwrap() };
+
+        state.paused
+    }
+
+    /// Ticks advanced since init.
+    #[no_mangle]
+    pub extern "C" fn elapsed_ticks() -> u64 {
+        let state = unsafe { STATE.as_ref().unwrap() };
+
+        state.elapsed_ticks
+    }
+
+    /// Generate coarse mesh of chunk. Result is valid until next call.
+    #[no_mangle]
+    pub extern "C" fn generate_lod_mesh(
+        cx: usize,
+        cy: usize,
+        cz: usize,
+        level: u32,
+    ) -> *const ExportMesh {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state
+            .update_lod_mesh([cx, cy, cz], level)
+            .map_or(ptr::null(), |m| m as *const _)
+    }
+
+    /// Changed ranges of meshes, in the same order as exported meshes.
+    #[no_mangle]
+    pub extern "C" fn mesh_diff() -> *const ExportMeshDiff {
+        let state = unsafe { STATE.as_ref().unwrap() };
+
+        state
+            .export_mesh_diff
+            .as_slice()
+            .expect("Data is not C-contiguous")
+            .as_ptr()
+    }
+
+    /// Take events accumulated since last call. Valid until next call.
+    #[no_mangle]
+    pub extern "C" fn get_events() -> *const Event {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.export_events.clear();
+        std::mem::swap(&mut state.events, &mut state.export_events);
+        state.export_events.as_ptr()
+    }
+
+    /// Number of events returned by last get_events().
+    #[no_mangle]
+    pub extern "C" fn event_count() -> usize {
+        let state = unsafe { STATE.as_ref().unwrap() };
+
+        state.export_events.len()
+    }
+
     #[no_mangle]
     pub extern "C" fn mark_all_dirty() {
         let state = unsafe { STATE.as_mut().unwrap() };
@@ -336,6 +1012,303 @@ const _: () = {
         }
     }
 
+    /// Allocate buffer of (x, y, z, block) entries for set_blocks().
+    #[no_mangle]
+    pub extern "C" fn alloc_set_blocks(count: usize) -> *mut [u32; 4] {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.block_cache.clear();
+        state.block_cache.resize(count, [0; 4]);
+        state.block_cache.as_mut_ptr()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn set_blocks() {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        let blocks = std::mem::take(&mut state.block_cache);
+        state.set_blocks(
+            blocks
+                .iter()
+                .map(|&[x, y, z, b]| ((x as usize, y as usize, z as usize), b)),
+        );
+        state.block_cache = blocks;
+    }
+
+    #[no_mangle]
+    pub extern "C" fn set_stack_size(item: u16, size: u8) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.set_stack_size(item, size);
+    }
+
+    #[no_mangle]
+    pub extern "C" fn set_drop_rate(block: u8, numerator: u32, denominator: u32) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.set_drop_rate(block, numerator, denominator);
+    }
+
+    /// Set whether faces facing outside of the world are generated.
+    #[no_mangle]
+    pub extern "C" fn set_mesh_boundary(boundary: bool) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        if state.mesh_boundary != boundary {
+            state.mesh_boundary = boundary;
+            for m in &mut state.mesh {
+                m.dirty = true;
+            }
+        }
+    }
+
+    /// Set whether mesh indices are grouped into ranges by block type.
+    #[no_mangle]
+    pub extern "C" fn set_mesh_split(split: bool) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        if state.mesh_split != split {
+            state.mesh_split = split;
+            for m in &mut state.mesh {
+                m.dirty = true;
+            }
+        }
+    }
+
+    /// Set whether mesh_diff() reports changed ranges instead of whole meshes.
+    #[no_mangle]
+    pub extern "C" fn set_mesh_diff(diff: bool) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.mesh_diff = diff;
+        if !diff {
+            for m in &mut state.mesh {
+                meshgen::clear_old_mesh(m);
+            }
+        }
+    }
+
+    /// Set whether mesh triangles use reversed (clockwise) winding.
+    #[no_mangle]
+    pub extern "C" fn set_reverse_winding(reverse: bool) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        if state.mesh_reverse_winding != reverse {
+            state.mesh_reverse_winding = reverse;
+            for m in &mut state.mesh {
+                m.dirty = true;
+            }
+        }
+    }
+
+    /// Set whether random ticks pick distinct cells of each chunk.
+    #[no_mangle]
+    pub extern "C" fn set_tick_shuffle(shuffle: bool) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.tick_shuffle = shuffle;
+    }
+
+    /// Set tick order: 0 = command first, 1 = environment first, 2 = interleave.
+    #[no_mangle]
+    pub extern "C" fn set_tick_order(order: u8) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.tick_order = match order {
+            0 => TickOrder::CommandFirst,
+            1 => TickOrder::EnvironmentFirst,
+            2 => TickOrder::Interleave,
+            _ => return,
+        };
+    }
+
+    /// Set whether chunk is frozen, excluded from random tick and drone edits.
+    #[no_mangle]
+    pub extern "C" fn set_frozen(cx: usize, cy: usize, cz: usize, frozen: bool) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        if let Some(f) = state.frozen.get_mut((cx, cy, cz)) {
+            *f = frozen;
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn set_growth_ticks(block: u8, ticks: u32) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.set_growth_ticks(block, ticks);
+    }
+
+    /// 64 byte buffer for export_rng() and import_rng().
+    #[no_mangle]
+    pub extern "C" fn rng_buffer() -> *mut u8 {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.rng_cache.as_mut_ptr()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn export_rng() {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.rng_cache = state.export_rng();
+    }
+
+    #[no_mangle]
+    pub extern "C" fn import_rng() {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.import_rng(state.rng_cache);
+    }
+
+    /// Place block along ray. Returns placed coordinate, or null on failure.
+    #[no_mangle]
+    pub extern "C" fn place_block_ray(
+        ox: f32,
+        oy: f32,
+        oz: f32,
+        dx: f32,
+        dy: f32,
+        dz: f32,
+        block: u8,
+    ) -> *const [usize; 3] {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        match state.place_block_ray(Vec3::new(ox, oy, oz), Vec3::new(dx, dy, dz), block) {
+            Some((x, y, z)) => {
+                state.ray_cache = [x, y, z];
+                &state.ray_cache
+            }
+            None => ptr::null(),
+        }
+    }
+
+    /// Compute distance field toward target, capped at max.
+    /// Returns pointer to one byte per cell, in level data layout.
+    #[no_mangle]
+    pub extern "C" fn query_distance_field(tx: usize, ty: usize, tz: usize, max: u8) -> *const u8 {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.query_distance_field((tx, ty, tz), max).as_ptr()
+    }
+
+    /// Buffer for export_face_list().
+    #[no_mangle]
+    pub extern "C" fn face_buffer() -> *const Face {
+        let state = unsafe { STATE.as_ref().unwrap() };
+
+        state.face_cache.as_ptr()
+    }
+
+    /// Export exposed faces of chunk. Returns number of faces.
+    #[no_mangle]
+    pub extern "C" fn export_face_list(x: usize, y: usize, z: usize) -> usize {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.export_face_list([x, y, z]).map_or(0, |v| v.len())
+    }
+
+    /// Buffer for export_inventory().
+    #[no_mangle]
+    pub extern "C" fn inventory_buffer() -> *const drone::Inventory {
+        let state = unsafe { STATE.as_ref().unwrap() };
+
+        state.inventory_cache.as_ptr()
+    }
+
+    /// Export inventory of drone i. Returns number of slots.
+    #[no_mangle]
+    pub extern "C" fn export_inventory(i: usize) -> usize {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.export_inventory(i).map_or(0, |v| v.len())
+    }
+
+    /// Set drop policy of drone i: 0 = stack, 1 = ordered, 2 = round robin.
+    #[no_mangle]
+    pub extern "C" fn set_drop_policy(i: usize, policy: u8) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        let policy = match policy {
+            0 => drone::DropPolicy::Stack,
+            1 => drone::DropPolicy::Ordered,
+            2 => drone::DropPolicy::RoundRobin,
+            _ => return,
+        };
+        if let Some(d) = state.drones.get_mut(i) {
+            (d.drop_policy, d.drop_cursor) = (policy, 0);
+        }
+    }
+
+    /// Set items per tick moved by transfer commands of drone i, zero is unlimited.
+    #[no_mangle]
+    pub extern "C" fn set_transfer_rate(i: usize, rate: u8) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        if let Some(d) = state.drones.get_mut(i) {
+            d.transfer_rate = rate;
+        }
+    }
+
+    /// Set how far break and place commands of drone i reach.
+    #[no_mangle]
+    pub extern "C" fn set_reach(i: usize, reach: u8) {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        if let Some(d) = state.drones.get_mut(i) {
+            d.reach = reach;
+        }
+    }
+
+    /// Set inventory size of drone. Shrinking moves items into remaining slots.
+    ///
+    /// Returns false if they don't fit or drone does not exist.
+    #[no_mangle]
+    pub extern "C" fn set_inventory_size(i: usize, size: u8) -> bool {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        match state.drones.get_mut(i) {
+            Some(d) => d.resize_inventory(size, |v| drone::stack_limit(&state.stack_size, v)),
+            None => false,
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn state_hash() -> u64 {
+        let state = unsafe { STATE.as_ref().unwrap() };
+
+        hash::state_hash(state)
+    }
+
+    /// Whether region [x0, x1) * [y0, y1) * [z0, z1) has no blocks.
+    #[no_mangle]
+    pub extern "C" fn is_region_air(
+        x0: usize,
+        y0: usize,
+        z0: usize,
+        x1: usize,
+        y1: usize,
+        z1: usize,
+    ) -> bool {
+        let state = unsafe { STATE.as_ref().unwrap() };
+
+        state.is_region_air([x0, y0, z0], [x1, y1, z1])
+    }
+
+    #[no_mangle]
+    pub extern "C" fn pending_command_count() -> usize {
+        let state = unsafe { STATE.as_ref().unwrap() };
+
+        state.pending_command_count()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn clear_all_commands() {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.clear_all_commands();
+    }
+
     #[no_mangle]
     pub extern "C" fn update_all_drones() {
         let state = unsafe { STATE.as_mut().unwrap() };
@@ -362,6 +1335,15 @@ const _: () = {
         }
     }
 
+    #[no_mangle]
+    pub extern "C" fn pubsub_peek(i: usize) {
+        let state = unsafe { STATE.as_ref().unwrap() };
+
+        if let Some((key, msg)) = state.pubsub[i].peek() {
+            unsafe { write_key_msg(key.len(), key.as_ptr(), msg.len(), msg.as_ptr()) };
+        }
+    }
+
     #[no_mangle]
     pub extern "C" fn pubsub_listen(i: usize, key_len: usize) {
         let state = unsafe { STATE.as_mut().unwrap() };
@@ -389,4 +1371,12 @@ const _: () = {
 
         state.pubsub.transfer();
     }
+
+    /// Transfer messages and get waiting message count of each subscriber.
+    #[no_mangle]
+    pub extern "C" fn pubsub_transfer_and_deliver() -> *const usize {
+        let state = unsafe { STATE.as_mut().unwrap() };
+
+        state.pubsub.transfer_and_deliver().as_ptr()
+    }
 };
@@ -4,29 +4,79 @@
 
 use std::f32::consts;
 use std::iter;
+use std::mem;
 
 use glam::f32::*;
-use ndarray::ArrayView3;
+use ndarray::{s, Array3, ArrayView3, Dimension};
 
-use super::blocks::{block_type, block_uv, BlockType};
-use super::Mesh;
+use super::blocks::{block_meta, block_type, block_uv, BlockType};
+use super::drone::Dir;
+use super::{Face, Mesh, MeshRange};
 
 const DIV_U: f32 = 1. / 16.0;
 const DIV_V: f32 = 1. / 16.0;
 
-pub fn gen_mesh(data: ArrayView3<u32>, size: usize, [sx, sy, sz]: [usize; 3], mesh: &mut Mesh) {
+/// Paint colors of full blocks, indexed by block metadata.
+const PALETTE: [Vec4; 16] = [
+    Vec4::new(1.0, 1.0, 1.0, 1.0),
+    Vec4::new(0.9, 0.2, 0.2, 1.0),
+    Vec4::new(0.9, 0.5, 0.1, 1.0),
+    Vec4::new(0.9, 0.9, 0.2, 1.0),
+    Vec4::new(0.5, 0.9, 0.2, 1.0),
+    Vec4::new(0.2, 0.7, 0.2, 1.0),
+    Vec4::new(0.2, 0.8, 0.8, 1.0),
+    Vec4::new(0.3, 0.6, 0.9, 1.0),
+    Vec4::new(0.2, 0.2, 0.9, 1.0),
+    Vec4::new(0.6, 0.3, 0.9, 1.0),
+    Vec4::new(0.9, 0.3, 0.9, 1.0),
+    Vec4::new(0.9, 0.6, 0.7, 1.0),
+    Vec4::new(0.5, 0.3, 0.2, 1.0),
+    Vec4::new(0.6, 0.6, 0.6, 1.0),
+    Vec4::new(0.3, 0.3, 0.3, 1.0),
+    Vec4::new(0.1, 0.1, 0.1, 1.0),
+];
+
+/// Whether face toward neighbor cell is visible.
+///
+/// Neighbor outside of world is only visible if boundary is set.
+fn is_face_visible(
+    data: ArrayView3<u32>,
+    boundary: bool,
+    c: Option<(usize, usize, usize)>,
+) -> bool {
+    match c.and_then(|c| data.get(c)) {
+        None => boundary,
+        Some(&b) => block_type((b & 0xff) as u8) != BlockType::Full,
+    }
+}
+
+pub fn gen_mesh(
+    data: ArrayView3<u32>,
+    size: usize,
+    [sx, sy, sz]: [usize; 3],
+    boundary: bool,
+    split: bool,
+    reverse: bool,
+    mesh: &mut Mesh,
+) {
     mesh.vertex.clear();
     mesh.normal.clear();
     mesh.tangent.clear();
     mesh.uv.clear();
+    mesh.color.clear();
     mesh.index.clear();
+    mesh.ranges.clear();
+    let mut runs = Vec::new();
 
     let ex = (sx + size).min(data.raw_dim()[0]);
     let ey = (sy + size).min(data.raw_dim()[1]);
     let ez = (sz + size).min(data.raw_dim()[2]);
+    let (wx, wy, wz) = data.raw_dim().into_pattern();
 
     let mut f = |x, y, z| {
         let b = (data[(x, y, z)] & 0xff) as u8;
+        let start = mesh.index.len();
+        let mut tint = Vec4::ONE;
         match block_type(b) {
             BlockType::Empty => return,
             BlockType::Blade => {
@@ -89,6 +139,7 @@ pub fn gen_mesh(data: ArrayView3<u32>, size: usize, [sx, sy, sz]: [usize; 3], me
                 );
             }
             BlockType::Full => {
+                tint = PALETTE[block_meta(data[(x, y, z)]) as usize];
                 let [u, v] = block_uv(b);
                 let u = (u as f32) * DIV_U;
                 let v = (v as f32) * DIV_V;
@@ -98,8 +149,8 @@ pub fn gen_mesh(data: ArrayView3<u32>, size: usize, [sx, sy, sz]: [usize; 3], me
                 let uv4 = Vec2::new(u + DIV_U, v + DIV_V);
 
                 // Up
-                if (y + 1 >= ey)
-                    || (block_type((data[(x, y + 1, z)] & 0xff) as u8) != BlockType::Full)
+                if ((y + 1 >= ey) && (y + 1 < wy))
+                    || is_face_visible(data, boundary, Some((x, y + 1, z)))
                 {
                     let i = mesh.vertex.len() as u32;
                     mesh.vertex.extend([
@@ -116,7 +167,7 @@ pub fn gen_mesh(data: ArrayView3<u32>, size: usize, [sx, sy, sz]: [usize; 3], me
                 }
 
                 // Down
-                if (y == 0) || (block_type((data[(x, y - 1, z)] & 0xff) as u8) != BlockType::Full) {
+                if is_face_visible(data, boundary, y.checked_sub(1).map(|y| (x, y, z))) {
                     let i = mesh.vertex.len() as u32;
                     mesh.vertex.extend([
                         Vec3::new((x - sx) as _, (y - sy) as _, (z - sz) as _),
@@ -132,8 +183,8 @@ pub fn gen_mesh(data: ArrayView3<u32>, size: usize, [sx, sy, sz]: [usize; 3], me
                 }
 
                 // Left
-                if (x + 1 >= ex)
-                    || (block_type((data[(x + 1, y, z)] & 0xff) as u8) != BlockType::Full)
+                if ((x + 1 >= ex) && (x + 1 < wx))
+                    || is_face_visible(data, boundary, Some((x + 1, y, z)))
                 {
                     let i = mesh.vertex.len() as u32;
                     mesh.vertex.extend([
@@ -150,7 +201,7 @@ pub fn gen_mesh(data: ArrayView3<u32>, size: usize, [sx, sy, sz]: [usize; 3], me
                 }
 
                 // Right
-                if (x == 0) || (block_type((data[(x - 1, y, z)] & 0xff) as u8) != BlockType::Full) {
+                if is_face_visible(data, boundary, x.checked_sub(1).map(|x| (x, y, z))) {
                     let i = mesh.vertex.len() as u32;
                     mesh.vertex.extend([
                         Vec3::new((x - sx) as _, (y - sy) as _, (z - sz) as _),
@@ -166,8 +217,8 @@ pub fn gen_mesh(data: ArrayView3<u32>, size: usize, [sx, sy, sz]: [usize; 3], me
                 }
 
                 // Back
-                if (z + 1 >= ez)
-                    || (block_type((data[(x, y, z + 1)] & 0xff) as u8) != BlockType::Full)
+                if ((z + 1 >= ez) && (z + 1 < wz))
+                    || is_face_visible(data, boundary, Some((x, y, z + 1)))
                 {
                     let i = mesh.vertex.len() as u32;
                     mesh.vertex.extend([
@@ -184,7 +235,7 @@ pub fn gen_mesh(data: ArrayView3<u32>, size: usize, [sx, sy, sz]: [usize; 3], me
                 }
 
                 // Front
-                if (z == 0) || (block_type((data[(x, y, z - 1)] & 0xff) as u8) != BlockType::Full) {
+                if is_face_visible(data, boundary, z.checked_sub(1).map(|z| (x, y, z))) {
                     let i = mesh.vertex.len() as u32;
                     mesh.vertex.extend([
                         Vec3::new((x - sx) as _, (y - sy) as _, (z - sz) as _),
@@ -200,12 +251,191 @@ pub fn gen_mesh(data: ArrayView3<u32>, size: usize, [sx, sy, sz]: [usize; 3], me
                 }
             }
         }
+        mesh.color.resize(mesh.vertex.len(), tint);
+        if split && start != mesh.index.len() {
+            runs.push((b, start..mesh.index.len()));
+        }
     };
+    // Skip empty chunk
+    if data
+        .slice(s![sx..ex, sy..ey, sz..ez])
+        .iter()
+        .any(|&b| block_type((b & 0xff) as u8) != BlockType::Empty)
+    {
+        for x in sx..ex {
+            for y in sy..ey {
+                for z in sz..ez {
+                    f(x, y, z);
+                }
+            }
+        }
+    }
+
+    // Group indices by block
+    if split {
+        runs.sort_by_key(|(b, _)| *b);
+        let mut index = Vec::with_capacity(mesh.index.len());
+        for (b, r) in runs {
+            let i = index.len() as u32;
+            index.extend_from_slice(&mesh.index[r]);
+            match mesh.ranges.last_mut() {
+                Some(v) if v.block == b as u32 => v.end = index.len() as _,
+                _ => mesh.ranges.push(MeshRange {
+                    block: b as _,
+                    start: i,
+                    end: index.len() as _,
+                }),
+            }
+        }
+        mesh.index = index;
+    }
+    // Flip front face of every triangle
+    if reverse {
+        for t in mesh.index.chunks_exact_mut(3) {
+            t.swap(1, 2);
+        }
+    }
+}
+
+/// Move current mesh data into old buffers, to diff against after regeneration.
+pub fn keep_old_mesh(mesh: &mut Mesh) {
+    mem::swap(&mut mesh.vertex, &mut mesh.old_vertex);
+    mem::swap(&mut mesh.normal, &mut mesh.old_normal);
+    mem::swap(&mut mesh.tangent, &mut mesh.old_tangent);
+    mem::swap(&mut mesh.uv, &mut mesh.old_uv);
+    mem::swap(&mut mesh.color, &mut mesh.old_color);
+    mem::swap(&mut mesh.index, &mut mesh.old_index);
+}
+
+/// Compute changed ranges against old buffers. Without them, everything is changed.
+pub fn diff_mesh(mesh: &mut Mesh) {
+    mesh.vertex_changed = changed_range(mesh.old_vertex.len(), mesh.vertex.len(), |i| {
+        (mesh.old_vertex[i] == mesh.vertex[i])
+            && (mesh.old_normal[i] == mesh.normal[i])
+            && (mesh.old_tangent[i] == mesh.tangent[i])
+            && (mesh.old_uv[i] == mesh.uv[i])
+            && (mesh.old_color[i] == mesh.color[i])
+    });
+    mesh.index_changed = changed_range(mesh.old_index.len(), mesh.index.len(), |i| {
+        mesh.old_index[i] == mesh.index[i]
+    });
+}
+
+/// Drop old buffers, when diffing is disabled.
+pub fn clear_old_mesh(mesh: &mut Mesh) {
+    mesh.old_vertex = Vec::new();
+    mesh.old_normal = Vec::new();
+    mesh.old_tangent = Vec::new();
+    mesh.old_uv = Vec::new();
+    mesh.old_color = Vec::new();
+    mesh.old_index = Vec::new();
+}
+
+/// List exposed faces of full blocks, for instancing a single quad.
+///
+/// Face positions are in level coordinates.
+pub fn gen_face_list(
+    data: ArrayView3<u32>,
+    size: usize,
+    [sx, sy, sz]: [usize; 3],
+    boundary: bool,
+    faces: &mut Vec<Face>,
+) {
+    const DIRS: [Dir; 6] = [
+        Dir::Up,
+        Dir::Down,
+        Dir::Left,
+        Dir::Right,
+        Dir::Front,
+        Dir::Back,
+    ];
+
+    faces.clear();
+    let dim = data.raw_dim().into_pattern();
+    let ex = (sx + size).min(dim.0);
+    let ey = (sy + size).min(dim.1);
+    let ez = (sz + size).min(dim.2);
     for x in sx..ex {
         for y in sy..ey {
             for z in sz..ez {
-                f(x, y, z);
+                let b = (data[(x, y, z)] & 0xff) as u8;
+                if block_type(b) != BlockType::Full {
+                    continue;
+                }
+
+                faces.extend(
+                    DIRS.into_iter()
+                        .filter(|d| is_face_visible(data, boundary, d.move_coord(&dim, (x, y, z))))
+                        .map(|dir| Face {
+                            x: x as _,
+                            y: y as _,
+                            z: z as _,
+                            dir,
+                            block: b,
+                        }),
+                );
             }
         }
     }
 }
+
+/// Generate coarse mesh, with each cell covering 2^level blocks per axis.
+///
+/// A coarse cell is solid if any block in it is a full block.
+pub fn gen_mesh_lod(
+    data: ArrayView3<u32>,
+    size: usize,
+    [sx, sy, sz]: [usize; 3],
+    level: u32,
+    reverse: bool,
+    mesh: &mut Mesh,
+) {
+    let scale = 1 << level;
+    let ex = (sx + size).min(data.raw_dim()[0]);
+    let ey = (sy + size).min(data.raw_dim()[1]);
+    let ez = (sz + size).min(data.raw_dim()[2]);
+
+    let shape = [
+        ex.saturating_sub(sx).div_ceil(scale),
+        ey.saturating_sub(sy).div_ceil(scale),
+        ez.saturating_sub(sz).div_ceil(scale),
+    ];
+    let lod = Array3::from_shape_fn(shape, |(x, y, z)| {
+        let (x, y, z) = (sx + x * scale, sy + y * scale, sz + z * scale);
+        data.slice(s![
+            x..(x + scale).min(ex),
+            y..(y + scale).min(ey),
+            z..(z + scale).min(ez),
+        ])
+        .iter()
+        .map(|&b| b & 0xff)
+        .find(|&b| block_type(b as u8) == BlockType::Full)
+        .unwrap_or(0)
+    });
+
+    gen_mesh(
+        lod.view(),
+        size.div_ceil(scale),
+        [0; 3],
+        true,
+        false,
+        reverse,
+        mesh,
+    );
+    for v in &mut mesh.vertex {
+        *v *= scale as f32;
+    }
+}
+
+/// Range of new buffer that differs from the old one.
+///
+/// If length changed, everything past the common prefix is changed.
+fn changed_range(old: usize, new: usize, mut eq: impl FnMut(usize) -> bool) -> [usize; 2] {
+    let n = old.min(new);
+    let start = (0..n).find(|&i| !eq(i)).unwrap_or(n);
+    if old != new {
+        return [start, new];
+    }
+    let end = (start..n).rev().find(|&i| !eq(i)).map_or(start, |i| i + 1);
+    [start, end]
+}
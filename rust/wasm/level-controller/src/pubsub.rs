@@ -10,10 +10,13 @@ use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
 
 const QUEUE_SIZE: usize = 64;
 
+/// Key and message pair.
+pub type Message = (Rc<[u8]>, Rc<[u8]>);
+
 #[derive(Debug, Default)]
 pub struct Subscriber {
-    in_queue: ConstGenericRingBuffer<(Rc<[u8]>, Rc<[u8]>), QUEUE_SIZE>,
-    out_queue: ConstGenericRingBuffer<(Rc<[u8]>, Rc<[u8]>), QUEUE_SIZE>,
+    in_queue: ConstGenericRingBuffer<Message, QUEUE_SIZE>,
+    out_queue: ConstGenericRingBuffer<Message, QUEUE_SIZE>,
 }
 
 impl Subscriber {
@@ -21,10 +24,24 @@ impl Subscriber {
         self.in_queue.enqueue((key, msg));
     }
 
-    pub fn pop(&mut self) -> Option<(Rc<[u8]>, Rc<[u8]>)> {
+    pub fn pop(&mut self) -> Option<Message> {
         self.out_queue.dequeue()
     }
 
+    /// Like pop(), but leaves the message queued.
+    pub fn peek(&self) -> Option<&Message> {
+        self.out_queue.peek()
+    }
+
+    /// Number of messages ready to pop.
+    pub fn len(&self) -> usize {
+        self.out_queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.out_queue.is_empty()
+    }
+
     fn transfer(&mut self) {
         self.out_queue.extend(self.in_queue.drain());
     }
@@ -34,6 +51,7 @@ impl Subscriber {
 pub struct PubSub {
     subscribers: Vec<Box<Subscriber>>,
     listeners: BTreeMap<Rc<[u8]>, Vec<usize>>,
+    counts: Vec<usize>,
 }
 
 impl Index<usize> for PubSub {
@@ -55,6 +73,7 @@ impl PubSub {
         Self {
             subscribers: Vec::new(),
             listeners: BTreeMap::new(),
+            counts: Vec::new(),
         }
     }
 
@@ -98,4 +117,15 @@ impl PubSub {
             s.transfer();
         }
     }
+
+    /// Transfer messages, then return number of waiting messages per subscriber.
+    pub fn transfer_and_deliver(&mut self) -> &[usize] {
+        self.counts.clear();
+        for s in &mut self.subscribers {
+            s.transfer();
+            self.counts.push(s.len());
+        }
+
+        &self.counts
+    }
 }
@@ -0,0 +1,72 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use glam::f32::*;
+use glam::I64Vec3;
+use ndarray::Array3;
+
+type Coord = (usize, usize, usize);
+
+/// Cast ray through grid cells (DDA), stopping at first cell satisfying predicate.
+///
+/// Cells outside of data are skipped. Returns hit cell and the cell before it,
+/// if ray did not start inside the hit cell. Gives up after max_steps cells.
+pub fn raycast<F>(
+    data: &Array3<u32>,
+    origin: Vec3,
+    dir: Vec3,
+    mut predicate: F,
+    max_steps: usize,
+) -> Option<(Coord, Option<Coord>)>
+where
+    F: FnMut(u32) -> bool,
+{
+    let dir = dir.try_normalize()?;
+    let get = |c: I64Vec3| -> Option<Coord> {
+        let c = (
+            usize::try_from(c.x).ok()?,
+            usize::try_from(c.y).ok()?,
+            usize::try_from(c.z).ok()?,
+        );
+        data.get(c).map(|_| c)
+    };
+
+    let mut cell = origin.floor().as_i64vec3();
+    let step = I64Vec3::new(
+        dir.x.signum() as _,
+        dir.y.signum() as _,
+        dir.z.signum() as _,
+    );
+    let delta = dir.recip().abs();
+    let next = Vec3::select(
+        dir.cmpgt(Vec3::ZERO),
+        cell.as_vec3() + Vec3::ONE - origin,
+        origin - cell.as_vec3(),
+    );
+    let mut t = Vec3::select(dir.cmpeq(Vec3::ZERO), Vec3::INFINITY, next * delta);
+
+    let mut prev = None;
+    for _ in 0..max_steps {
+        let c = get(cell);
+        if let Some(c) = c {
+            if predicate(data[c]) {
+                return Some((c, prev));
+            }
+        }
+        prev = c;
+
+        if t.x < t.y && t.x < t.z {
+            cell.x += step.x;
+            t.x += delta.x;
+        } else if t.y < t.z {
+            cell.y += step.y;
+            t.y += delta.y;
+        } else {
+            cell.z += step.z;
+            t.z += delta.z;
+        }
+    }
+
+    None
+}
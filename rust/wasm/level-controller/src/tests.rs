@@ -5,6 +5,8 @@
 use super::drone::*;
 use super::*;
 
+use std::num::NonZeroU16;
+
 use anyhow::Error;
 use itertools::Itertools as _;
 
@@ -376,3 +378,1498 @@ fn test_move_fail_tree() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn test_random_tick_partial_chunk() -> Result<(), Error> {
+    // Level size is not a multiple of chunk size
+    let mut state = State::new(SEED, [20, 4, 20], 16, 1, 4);
+    state.data[(18, 1, 18)] = 1;
+    for m in &mut state.mesh {
+        m.dirty = true;
+    }
+
+    state.random_tick();
+    assert_eq!(state.tick_chunks, [(1, 0, 1)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_drop_rate_always() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 1, 1);
+    state.set_drop_rate(1, 1, 1);
+
+    for i in 1..=16 {
+        state.data[(1, 0, 0)] = 1;
+        state.drones[0].command = Command::BreakBlock(Dir::Left);
+
+        execute_commands(&mut state);
+
+        assert_eq!(state.data[(1, 0, 0)] & 0xff, 0);
+        assert_eq!(state.drones[0].inventory[0].item_id, NonZeroU16::new(1));
+        assert_eq!(state.drones[0].inventory[0].count, i);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_drop_rate_never() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 1, 1);
+    state.set_drop_rate(1, 0, 1);
+
+    for _ in 0..16 {
+        state.data[(1, 0, 0)] = 1;
+        state.drones[0].command = Command::BreakBlock(Dir::Left);
+
+        execute_commands(&mut state);
+
+        assert_eq!(state.data[(1, 0, 0)] & 0xff, 0);
+        assert!(state.drones[0]
+            .inventory
+            .iter()
+            .all(|v| v.item_id.is_none()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_swap() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 2], 16, 2, 1);
+
+    fn f(state: &mut State, a: usize, b: usize, cmd: Command) {
+        state.drones[a] = Drone {
+            x: 0,
+            y: 0,
+            z: 0,
+            command: Command::Swap(Dir::Left),
+            ..Drone::default()
+        };
+        state.drones[b] = Drone {
+            x: 1,
+            y: 0,
+            z: 0,
+            command: cmd,
+            ..Drone::default()
+        };
+        update_all_drones(state);
+
+        execute_commands(state);
+
+        print_all_drone_coords(state);
+        assert_eq!(state.drones[a].x, 1);
+        assert_eq!(state.drones[a].y, 0);
+        assert_eq!(state.drones[a].z, 0);
+        assert_eq!(state.drones[b].x, 0);
+        assert_eq!(state.drones[b].y, 0);
+        assert_eq!(state.drones[b].z, 0);
+        assert!((state.data[(0, 0, 0)] & OCCUPIED_FLAG) != 0);
+        assert!((state.data[(1, 0, 0)] & OCCUPIED_FLAG) != 0);
+    }
+
+    f(&mut state, 0, 1, Command::Noop);
+    f(&mut state, 1, 0, Command::Noop);
+    f(&mut state, 0, 1, Command::Swap(Dir::Right));
+    f(&mut state, 1, 0, Command::Swap(Dir::Right));
+
+    Ok(())
+}
+
+#[test]
+fn test_swap_fail() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 2], 16, 2, 1);
+
+    fn f(state: &mut State, cmd: Command) {
+        state.drones[0] = Drone {
+            x: 0,
+            y: 0,
+            z: 0,
+            command: Command::Swap(Dir::Left),
+            ..Drone::default()
+        };
+        state.drones[1] = Drone {
+            x: 1,
+            y: 0,
+            z: 0,
+            command: cmd,
+            ..Drone::default()
+        };
+        update_all_drones(state);
+
+        execute_commands(state);
+
+        print_all_drone_coords(state);
+        assert_eq!(state.drones[0].x, 0);
+        assert_eq!(state.drones[0].y, 0);
+        assert_eq!(state.drones[0].z, 0);
+    }
+
+    // Target is moving
+    f(&mut state, Command::Move(Dir::Back));
+    // Target is swapping with something else
+    f(&mut state, Command::Swap(Dir::Back));
+
+    // Swap with a block
+    state.drones[0] = Drone {
+        x: 0,
+        y: 0,
+        z: 0,
+        command: Command::Swap(Dir::Back),
+        ..Drone::default()
+    };
+    state.drones[1] = Drone {
+        x: 1,
+        y: 0,
+        z: 1,
+        ..Drone::default()
+    };
+    state.data[(0, 0, 1)] = 1;
+    update_all_drones(&mut state);
+
+    execute_commands(&mut state);
+
+    print_all_drone_coords(&state);
+    assert_eq!(state.drones[0].x, 0);
+    assert_eq!(state.drones[0].y, 0);
+    assert_eq!(state.drones[0].z, 0);
+    assert_eq!(state.data[(0, 0, 1)] & 0xff, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_block_meta() -> Result<(), Error> {
+    let b = 2 | OCCUPIED_FLAG;
+    assert_eq!(blocks::block_meta(b), 0);
+
+    for m in 0..16 {
+        let b = blocks::set_block_meta(b, m);
+        assert_eq!(blocks::block_meta(b), m);
+        assert_eq!(b & 0xff, 2);
+        assert_ne!(b & OCCUPIED_FLAG, 0);
+    }
+
+    // Only the low 4 bits are stored
+    let b = blocks::set_block_meta(b, 0xfa);
+    assert_eq!(blocks::block_meta(b), 0xa);
+    assert_eq!(b & 0xff, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_block_meta_break() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 1, 1);
+
+    state.data[(1, 0, 0)] = blocks::set_block_meta(1, 5);
+    state.drones[0].command = Command::BreakBlock(Dir::Left);
+
+    execute_commands(&mut state);
+
+    assert_eq!(state.data[(1, 0, 0)], 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_reach_break() -> Result<(), Error> {
+    let mut state = State::new(SEED, [4, 1, 1], 16, 1, 1);
+    state.drones[0].reach = 2;
+
+    state.data[(2, 0, 0)] = 1;
+    state.drones[0].command = Command::BreakBlock(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::Ok);
+    assert_eq!(state.data[(2, 0, 0)], 0);
+
+    // Line of sight is blocked by nearer block
+    state.data[(1, 0, 0)] = 1;
+    state.data[(2, 0, 0)] = 1;
+    state.drones[0].command = Command::BreakBlock(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::Blocked);
+    assert_eq!(state.data[(1, 0, 0)], 1);
+    assert_eq!(state.data[(2, 0, 0)], 1);
+
+    state.data[(1, 0, 0)] = 0;
+    state.data[(2, 0, 0)] = 0;
+    state.drones[0].reach = 4;
+    state.drones[0].command = Command::BreakBlock(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::OutOfBounds);
+
+    Ok(())
+}
+
+#[test]
+fn test_random_tick_active_chunk() -> Result<(), Error> {
+    let mut state = State::new(SEED, [16, 4, 16], 4, 0, 64);
+
+    state.random_tick();
+    assert!(state.tick_chunks.is_empty());
+
+    state.data[(5, 1, 9)] = 1;
+    for m in &mut state.mesh {
+        m.dirty = true;
+    }
+    state.random_tick();
+    assert_eq!(state.tick_chunks, [(1, 0, 2)]);
+
+    let size = state.data.raw_dim().into_pattern();
+    for _ in 0..1024 {
+        let (x, y, z) =
+            sample_tick(&mut state.rng, &state.tick_chunks, state.chunks_size, size).unwrap();
+        assert!((4..8).contains(&x), "x: {x}");
+        assert!((0..4).contains(&y), "y: {y}");
+        assert!((8..12).contains(&z), "z: {z}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sample_tick_distinct() -> Result<(), Error> {
+    let mut state = State::new(SEED, [8, 3, 4], 4, 1, 1);
+    let size = state.data.raw_dim().into_pattern();
+    let chunks = [(0, 0, 0), (1, 0, 0)];
+
+    for _ in 0..16 {
+        let v = sample_tick_distinct(&mut state.rng, &chunks, 4, size, 40);
+        assert_eq!(v.len(), 40);
+        assert_eq!(v.iter().unique().count(), 40);
+        assert!(v.iter().all(|&(x, y, z)| x < 8 && y < 3 && z < 4));
+    }
+
+    // Capped at number of cells
+    let v = sample_tick_distinct(&mut state.rng, &chunks, 4, size, 1000);
+    assert_eq!(v.len(), 8 * 3 * 4);
+    assert_eq!(v.iter().unique().count(), 8 * 3 * 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_state_hash() -> Result<(), Error> {
+    fn f(seed: u64, flip: bool) -> State {
+        let mut state = State::new(seed, [4, 4, 4], 16, 2, 1);
+        let (a, b) = if flip { (1, 0) } else { (0, 1) };
+        state.drones[a] = Drone {
+            x: 1,
+            y: 2,
+            z: 3,
+            ..Drone::default()
+        };
+        state.drones[b] = Drone {
+            x: 3,
+            y: 0,
+            z: 0,
+            command: Command::Move(Dir::Up),
+            ..Drone::default()
+        };
+        state.data[(2, 2, 2)] = 1;
+        update_all_drones(&mut state);
+        state
+    }
+
+    let mut a = f(SEED, false);
+    let b = f(SEED ^ 1, true);
+    let h = hash::state_hash(&a);
+    assert_eq!(h, hash::state_hash(&a));
+    assert_eq!(h, hash::state_hash(&b));
+
+    a.data[(2, 2, 2)] = 2;
+    assert_ne!(h, hash::state_hash(&a));
+    a.data[(2, 2, 2)] = 1;
+    assert_eq!(h, hash::state_hash(&a));
+
+    a.drones[0].inventory[0] = Inventory::new(NonZeroU16::new(1), 1);
+    assert_ne!(h, hash::state_hash(&a));
+
+    Ok(())
+}
+
+#[test]
+fn test_inventory_size() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 1, 1);
+    state.drones[0].inventory_size = 1;
+    state.drones[0].inventory[0] = Inventory::new(NonZeroU16::new(2), 1);
+    state.data[(1, 0, 0)] = 1;
+    state.drones[0].command = Command::BreakBlock(Dir::Left);
+
+    execute_commands(&mut state);
+
+    assert_eq!(state.drones[0].inventory().len(), 1);
+    assert!(state.drones[0].inventory[1..]
+        .iter()
+        .all(|v| v.item_id.is_none()));
+
+    state.data[(1, 0, 0)] = 0;
+    state.drones[0].inventory[1] = Inventory::new(NonZeroU16::new(1), 1);
+    state.drones[0].command = Command::PlaceBlock(Dir::Left, 1);
+
+    execute_commands(&mut state);
+
+    assert_eq!(state.data[(1, 0, 0)] & 0xff, 0);
+    assert_eq!(state.drones[0].inventory[1].count, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_inventory_shrink() -> Result<(), Error> {
+    let mut state = State::new(SEED, [1, 1, 1], 16, 1, 1);
+    let d = &mut state.drones[0];
+    d.inventory[0] = Inventory::new(NonZeroU16::new(1), 60);
+    d.inventory[2] = Inventory::new(NonZeroU16::new(1), 4);
+    d.inventory[3] = Inventory::new(NonZeroU16::new(2), 5);
+
+    assert!(d.resize_inventory(2, |_| Inventory::MAX_STACK));
+    assert_eq!(d.inventory().len(), 2);
+    assert_eq!(d.inventory[0].count, 64);
+    assert_eq!(d.inventory[1].item_id, NonZeroU16::new(2));
+    assert_eq!(d.inventory[1].count, 5);
+    assert!(d.inventory[2..].iter().all(|v| v.item_id.is_none()));
+
+    d.inventory_size = 4;
+    d.inventory[3] = Inventory::new(NonZeroU16::new(1), 6);
+    assert!(!d.resize_inventory(2, |_| Inventory::MAX_STACK));
+    assert_eq!(d.inventory_size, 4);
+    assert_eq!(d.inventory[0].count, 64);
+    assert_eq!(d.inventory[1].count, 5);
+    assert_eq!(d.inventory[3].count, 6);
+
+    Ok(())
+}
+
+#[test]
+fn test_mesh_diff() -> Result<(), Error> {
+    let mut state = State::new(SEED, [16, 16, 16], 16, 0, 1);
+    state.data.slice_mut(s![..8, ..4, ..8]).fill(1);
+
+    state.update_mesh(usize::MAX);
+    state.mesh[(0, 0, 0)].dirty = true;
+    state.update_mesh(usize::MAX);
+    let m = &state.mesh[(0, 0, 0)];
+    assert!(m.old_vertex.is_empty());
+    assert_eq!(m.vertex_changed, [0, m.vertex.len()]);
+    assert_eq!(m.index_changed, [0, m.index.len()]);
+
+    state.mesh_diff = true;
+
+    state.data[(4, 3, 4)] = 2;
+    state.mesh[(0, 0, 0)].dirty = true;
+    state.update_mesh(usize::MAX);
+    let m = &state.mesh[(0, 0, 0)];
+    let [s, e] = m.vertex_changed;
+    assert!(s < e);
+    assert!((e - s) * 16 < m.vertex.len());
+    assert_eq!(m.index_changed[0], m.index_changed[1]);
+
+    state.mesh[(0, 0, 0)].dirty = true;
+    state.update_mesh(usize::MAX);
+    let m = &state.mesh[(0, 0, 0)];
+    assert_eq!(m.vertex_changed[0], m.vertex_changed[1]);
+
+    Ok(())
+}
+
+#[test]
+fn test_set_blocks() -> Result<(), Error> {
+    let mut state = State::new(SEED, [32, 16, 32], 16, 1, 1);
+    for m in &mut state.mesh {
+        m.dirty = false;
+    }
+
+    state.set_blocks(
+        (0..4)
+            .cartesian_product(0..2)
+            .cartesian_product(0..20)
+            .map(|((x, y), z)| ((x, y, z), 1))
+            .chain([((0, 0, 0), 2), ((99, 0, 0), 1)]),
+    );
+
+    assert_eq!(state.data[(0, 0, 0)], 2 | OCCUPIED_FLAG);
+    assert_eq!(state.data[(3, 1, 19)], 1);
+    assert_eq!(state.data[(4, 0, 0)], 0);
+    assert_eq!(state.data.iter().filter(|&&b| (b & 0xff) != 0).count(), 160);
+    for ((x, y, z), m) in state.mesh.indexed_iter() {
+        assert_eq!(m.dirty, (x == 0) && (y == 0), "chunk {x} {y} {z}");
+    }
+    assert!(state.tickable[(0, 0, 0)]);
+    assert!(state.tickable[(0, 0, 1)]);
+    assert!(!state.tickable[(1, 0, 0)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_mesh_budgeted() -> Result<(), Error> {
+    let mut state = State::new(SEED, [64, 16, 48], 16, 0, 1);
+    state.data.fill(1);
+    assert_eq!(state.mesh.len(), 12);
+
+    assert_eq!(state.update_mesh(5), 7);
+    assert_eq!(state.mesh.iter().filter(|m| m.updated).count(), 5);
+    assert_eq!(state.update_mesh(5), 2);
+    assert_eq!(state.mesh.iter().filter(|m| m.updated).count(), 10);
+    assert_eq!(state.update_mesh(5), 0);
+    assert!(state.mesh.iter().all(|m| m.updated && !m.dirty));
+    assert!(state.mesh.iter().all(|m| !m.index.is_empty()));
+    assert_eq!(state.update_mesh(5), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_mesh_empty_chunk() -> Result<(), Error> {
+    let mut state = State::new(SEED, [32, 16, 16], 16, 1, 1);
+    state.mesh_diff = true;
+    state.data[(20, 0, 0)] = 1;
+
+    state.update_mesh(usize::MAX);
+    let m = &state.mesh[(0, 0, 0)];
+    assert!(m.vertex.is_empty());
+    assert!(m.index.is_empty());
+    assert!(!state.mesh[(1, 0, 0)].vertex.is_empty());
+
+    state.data[(20, 0, 0)] = 0;
+    state.mesh[(1, 0, 0)].dirty = true;
+    state.update_mesh(usize::MAX);
+    let m = &state.mesh[(1, 0, 0)];
+    assert!(m.vertex.is_empty());
+    assert_eq!(m.vertex_changed, [0, 0]);
+
+    Ok(())
+}
+
+#[test]
+fn test_pubsub_transfer_and_deliver() -> Result<(), Error> {
+    let mut pubsub = pubsub::PubSub::new();
+    pubsub.add_subscribers(3);
+    pubsub.subscriber_listen(0, &b"a"[..]);
+    pubsub.subscriber_listen(1, &b"a"[..]);
+    pubsub.subscriber_listen(1, &b"b"[..]);
+    pubsub.subscriber_listen(2, &b"c"[..]);
+
+    pubsub.publish(b"a", &b"1"[..]);
+    pubsub.publish(b"b", &b"2"[..]);
+    pubsub.publish(b"b", &b"3"[..]);
+    pubsub.publish(b"d", &b"4"[..]);
+    assert!(pubsub[1].is_empty());
+
+    assert_eq!(pubsub.transfer_and_deliver(), [1, 3, 0]);
+    let (key, msg) = pubsub[1].pop().unwrap();
+    assert_eq!((&*key, &*msg), (&b"a"[..], &b"1"[..]));
+    assert_eq!(pubsub.transfer_and_deliver(), [1, 2, 0]);
+
+    Ok(())
+}
+
+#[test]
+fn test_pubsub_peek() -> Result<(), Error> {
+    let mut pubsub = pubsub::PubSub::new();
+    pubsub.add_subscribers(1);
+    pubsub.subscriber_listen(0, &b"a"[..]);
+    pubsub.publish(b"a", &b"1"[..]);
+    pubsub.publish(b"a", &b"2"[..]);
+    pubsub.transfer();
+
+    let (key, msg) = pubsub[0].peek().cloned().unwrap();
+    assert_eq!((&*key, &*msg), (&b"a"[..], &b"1"[..]));
+    assert_eq!(pubsub[0].len(), 2);
+    let (key, msg) = pubsub[0].pop().unwrap();
+    assert_eq!((&*key, &*msg), (&b"a"[..], &b"1"[..]));
+    let (_, msg) = pubsub[0].peek().cloned().unwrap();
+    assert_eq!(&*msg, b"2");
+    pubsub[0].pop();
+    assert!(pubsub[0].peek().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_harvest() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 2, 1], 16, 1, 1);
+    state.drones[0].y = 1;
+    update_all_drones(&mut state);
+    state.data[(1, 0, 0)] = 1;
+
+    // Not mature
+    state.data[(1, 1, 0)] = blocks::set_block_meta(blocks::CROP as _, 3);
+    state.drones[0].command = Command::Harvest(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.data[(1, 1, 0)] & 0xff, blocks::CROP as u32);
+    assert!(state.drones[0].inventory[0].item_id.is_none());
+
+    // No seed to replant
+    state.data[(1, 1, 0)] = blocks::set_block_meta(blocks::CROP as _, blocks::CROP_MATURE);
+    state.set_drop_rate(blocks::CROP, 0, 1);
+    state.drones[0].command = Command::Harvest(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.data[(1, 1, 0)], 0);
+
+    // Replant with harvested seed
+    state.data[(1, 1, 0)] = blocks::set_block_meta(blocks::CROP as _, blocks::CROP_MATURE);
+    state.set_drop_rate(blocks::CROP, 1, 1);
+    state.drones[0].command = Command::Harvest(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.data[(1, 1, 0)], blocks::CROP as u32);
+    // Dropped 1-3 seeds, one of them replanted
+    assert!(state.drones[0].inventory[0].count <= 2);
+    assert!(state.drones[0].inventory[1..]
+        .iter()
+        .all(|v| v.item_id.is_none()));
+
+    // Seed from inventory
+    state.data[(1, 1, 0)] = blocks::set_block_meta(blocks::CROP as _, blocks::CROP_MATURE);
+    state.set_drop_rate(blocks::CROP, 0, 1);
+    state.drones[0].inventory[0] = Inventory::new(NonZeroU16::new(blocks::CROP_SEED), 1);
+    state.drones[0].command = Command::Harvest(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.data[(1, 1, 0)], blocks::CROP as u32);
+    assert!(state.drones[0].inventory[0].item_id.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_lod_mesh() -> Result<(), Error> {
+    let mut state = State::new(SEED, [32, 16, 16], 16, 1, 1);
+    state.data.slice_mut(s![..16, .., ..]).fill(1);
+
+    state.update_mesh(usize::MAX);
+    let n0 = state.mesh[(0, 0, 0)].vertex.len();
+
+    let m = state.update_lod_mesh([0, 0, 0], 0).unwrap();
+    assert_eq!(m.vertex_count, n0);
+    let m = state.update_lod_mesh([0, 0, 0], 1).unwrap();
+    assert_eq!(m.vertex_count * 4, n0);
+    let v = &state.lod_mesh.vertex;
+    assert!(v.iter().all(|v| v.max_element() <= 16.));
+    assert!(v.iter().any(|v| v.max_element() == 16.));
+
+    let m = state.update_lod_mesh([1, 0, 0], 2).unwrap();
+    assert_eq!((m.x, m.vertex_count), (16, 0));
+    assert!(state.update_lod_mesh([2, 0, 0], 1).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_inventory_data_hash() -> Result<(), Error> {
+    let id = NonZeroU16::new(1);
+    let a = Inventory::new(id, 10);
+    let b = Inventory::new(id, 10).with_data_hash(1);
+    assert_eq!(std::mem::size_of::<Inventory>(), 4);
+
+    let mut inv = [a, Inventory::default()];
+    let mut c = b;
+    Inventory::try_put_one(&mut inv, &mut c);
+    assert!(c.item_id.is_none());
+    assert_eq!(inv[0].count, 10);
+    assert_eq!((inv[1].count, inv[1].data_hash), (10, 1));
+
+    let mut inv = [a, Inventory::default(), Inventory::default()];
+    Inventory::try_put_many(&mut inv, &mut [b, a, b]);
+    assert_eq!((inv[0].count, inv[0].data_hash), (20, 0));
+    assert_eq!((inv[1].count, inv[1].data_hash), (20, 1));
+    assert!(inv[2].item_id.is_none());
+
+    let mut state = State::new(SEED, [1, 1, 1], 16, 1, 1);
+    state.drones[0].inventory[..5].copy_from_slice(&[a, b, a, b, a]);
+    state.drones[0].command = Command::Restack;
+    execute_commands(&mut state);
+    let inv = &state.drones[0].inventory;
+    assert_eq!((inv[0].count, inv[0].data_hash), (30, 0));
+    assert_eq!((inv[1].count, inv[1].data_hash), (20, 1));
+    assert!(inv[2..].iter().all(|v| v.item_id.is_none()));
+
+    Ok(())
+}
+
+#[test]
+fn test_growth_ticks() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 2, 2], 16, 1, 64);
+    state.data[(1, 0, 0)] = blocks::CROP as _;
+    state.data[(1, 0, 1)] = blocks::CROP as _;
+    state.set_growth_ticks(blocks::CROP, 10);
+
+    for i in 1..10 {
+        state.random_tick();
+        assert!(!blocks::is_mature_crop(state.data[(1, 0, 0)]));
+        if i == 5 {
+            // Replant, restarts its timer
+            state.data[(1, 0, 1)] = blocks::CROP as _;
+        }
+    }
+    state.random_tick();
+    assert!(blocks::is_mature_crop(state.data[(1, 0, 0)]));
+    assert!(!blocks::is_mature_crop(state.data[(1, 0, 1)]));
+
+    for _ in 0..5 {
+        state.random_tick();
+    }
+    assert!(blocks::is_mature_crop(state.data[(1, 0, 1)]));
+    assert!(state.growth_timer.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_growth_ticks_non_crop() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 2, 2], 16, 1, 64);
+    state.data[(0, 0, 0)] = blocks::CROP as _;
+    state.set_growth_ticks(1, 10);
+    assert!(state.growth_ticks.iter().all(|&v| v == 0));
+
+    state.set_growth_ticks(blocks::CROP, 10);
+    state.random_tick();
+    assert!(state.growth_timer.contains_key(&(0, 0, 0)));
+
+    // Timer is dropped once crop is gone
+    state.data[(0, 0, 0)] = 0;
+    state.random_tick();
+    assert!(state.growth_timer.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_growth_ticks_resume() -> Result<(), Error> {
+    let mut state = State::new(SEED, [3, 1, 1], 1, 1, 64);
+    state.data[(1, 0, 0)] = blocks::CROP as _;
+    // Grown before timers were enabled
+    state.data[(2, 0, 0)] = blocks::set_block_meta(blocks::CROP as _, 2);
+    state.set_growth_ticks(blocks::CROP, 10);
+
+    // Timers are kept while frozen
+    for _ in 0..2 {
+        state.random_tick();
+    }
+    state.frozen[(1, 0, 0)] = true;
+    state.frozen[(2, 0, 0)] = true;
+    for _ in 0..3 {
+        state.random_tick();
+    }
+    assert_eq!(blocks::block_meta(state.data[(1, 0, 0)]), 1);
+    assert_eq!(blocks::block_meta(state.data[(2, 0, 0)]), 3);
+    state.frozen.fill(false);
+
+    for _ in 0..4 {
+        state.random_tick();
+    }
+    assert!(!blocks::is_mature_crop(state.data[(2, 0, 0)]));
+    state.random_tick();
+    assert!(blocks::is_mature_crop(state.data[(2, 0, 0)]));
+    for _ in 0..2 {
+        state.random_tick();
+    }
+    assert!(!blocks::is_mature_crop(state.data[(1, 0, 0)]));
+    state.random_tick();
+    assert!(blocks::is_mature_crop(state.data[(1, 0, 0)]));
+
+    Ok(())
+}
+
+#[test]
+fn test_flood_fill() -> Result<(), Error> {
+    let mut data = Array3::zeros([20, 4, 20]);
+    data.slice_mut(s![.., ..2, ..]).fill(1);
+
+    let v = fill::flood_fill(&data, (0, 0, 0), |b| b == 1, usize::MAX);
+    assert_eq!(v.len(), 20 * 2 * 20);
+    assert!(v.iter().all(|&c| data[c] == 1));
+
+    let v = fill::flood_fill(&data, (0, 0, 0), |b| b == 1, 100);
+    assert_eq!(v.len(), 100);
+    assert_eq!(v.iter().unique().count(), 100);
+    assert!(v.iter().all(|&(x, y, z)| x + y + z <= 9));
+
+    // Wall splits region in half
+    data.slice_mut(s![10, .., ..]).fill(2);
+    let v = fill::flood_fill(&data, (19, 1, 19), |b| b == 1, usize::MAX);
+    assert_eq!(v.len(), 9 * 2 * 20);
+    assert!(v.iter().all(|&(x, _, _)| x > 10));
+
+    assert!(fill::flood_fill(&data, (10, 0, 0), |b| b == 1, usize::MAX).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_distance_field() -> Result<(), Error> {
+    // Serpentine maze with walls at z = 1 and z = 3
+    let mut data = Array3::zeros([5, 1, 5]);
+    data.slice_mut(s![..4, .., 1]).fill(1);
+    data.slice_mut(s![1.., .., 3]).fill(1);
+
+    let v = fill::distance_field(&data, (0, 0, 0), |b| b == 0, 255);
+    let path = (0..5)
+        .map(|x| (x, 0, 0))
+        .chain([(4, 0, 1)])
+        .chain((0..5).rev().map(|x| (x, 0, 2)))
+        .chain([(0, 0, 3)])
+        .chain((0..5).map(|x| (x, 0, 4)));
+    for (i, c) in path.enumerate() {
+        assert_eq!(v[c] as usize, i, "{c:?}");
+    }
+    for (c, &b) in data.indexed_iter() {
+        if b != 0 {
+            assert_eq!(v[c], 255, "{c:?}");
+        }
+    }
+
+    let v = fill::distance_field(&data, (0, 0, 0), |b| b == 0, 8);
+    assert_eq!(v[(4, 0, 2)], 6);
+    assert_eq!(v[(0, 0, 2)], 8);
+    assert_eq!(v[(4, 0, 4)], 8);
+
+    Ok(())
+}
+
+#[test]
+fn test_command_result() -> Result<(), Error> {
+    let mut state = State::new(SEED, [3, 1, 1], 16, 2, 1);
+    state.data[(2, 0, 0)] = 1;
+
+    fn f(state: &mut State, cmd: Command, result: CommandResult) {
+        state.drones[0].command = cmd;
+        execute_commands(state);
+        print_all_drone_coords(state);
+        assert_eq!(state.drones[0].result, result, "{cmd:?}");
+        assert_eq!(state.drones[1].result, CommandResult::None);
+    }
+
+    f(&mut state, Command::Noop, CommandResult::None);
+    f(
+        &mut state,
+        Command::Move(Dir::Right),
+        CommandResult::OutOfBounds,
+    );
+    f(
+        &mut state,
+        Command::Move(Dir::Up),
+        CommandResult::OutOfBounds,
+    );
+    f(&mut state, Command::Move(Dir::Left), CommandResult::Blocked);
+    f(
+        &mut state,
+        Command::BreakBlock(Dir::Up),
+        CommandResult::OutOfBounds,
+    );
+    f(
+        &mut state,
+        Command::BreakBlock(Dir::Left),
+        CommandResult::NoTarget,
+    );
+    f(
+        &mut state,
+        Command::PlaceBlock(Dir::Left, 0),
+        CommandResult::NoItem,
+    );
+    f(
+        &mut state,
+        Command::PlaceBlock(Dir::Left, 99),
+        CommandResult::NoItem,
+    );
+    f(
+        &mut state,
+        Command::SendItem(Dir::Left, 0),
+        CommandResult::NoItem,
+    );
+    f(
+        &mut state,
+        Command::RecvItem(Dir::Right, 0),
+        CommandResult::OutOfBounds,
+    );
+    f(
+        &mut state,
+        Command::RecvItem(Dir::Left, 0),
+        CommandResult::Ok,
+    );
+    f(
+        &mut state,
+        Command::Swap(Dir::Right),
+        CommandResult::OutOfBounds,
+    );
+    f(
+        &mut state,
+        Command::Harvest(Dir::Left),
+        CommandResult::NoTarget,
+    );
+
+    state.drones[0].inventory[0] = Inventory::new(NonZeroU16::new(1), 1);
+    f(
+        &mut state,
+        Command::PlaceBlock(Dir::Left, 0),
+        CommandResult::Blocked,
+    );
+    state.drones[0].inventory_size = 0;
+    f(
+        &mut state,
+        Command::PlaceBlock(Dir::Left, 0),
+        CommandResult::NoItem,
+    );
+
+    state.drones[0].command = Command::Swap(Dir::Left);
+    state.drones[1].command = Command::Move(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::Blocked);
+    assert_eq!(state.drones[1].result, CommandResult::Blocked);
+
+    Ok(())
+}
+
+#[test]
+fn test_mesh_boundary() -> Result<(), Error> {
+    let mut state = State::new(SEED, [32, 16, 16], 16, 0, 1);
+    state.data.fill(1);
+
+    state.update_mesh(usize::MAX);
+    // 5 world faces and 1 chunk face
+    assert_eq!(state.mesh[(0, 0, 0)].vertex.len(), 6 * 16 * 16 * 4);
+
+    state.mesh_boundary = false;
+    for m in &mut state.mesh {
+        m.dirty = true;
+    }
+    state.update_mesh(usize::MAX);
+    let m = &state.mesh[(0, 0, 0)];
+    assert_eq!(m.vertex.len(), 16 * 16 * 4);
+    // Only the face toward chunk (1, 0, 0) remains
+    assert!(m.normal.iter().all(|&v| v == Vec3::X));
+    assert!(m.vertex.iter().all(|v| v.x == 16.));
+    let m = &state.mesh[(1, 0, 0)];
+    assert!(m.vertex.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_rng_resume() -> Result<(), Error> {
+    fn f() -> State {
+        let mut state = State::new(SEED, [16, 4, 16], 16, 1, 64);
+        state.data.slice_mut(s![.., ..2, ..]).fill(1);
+        state.data.slice_mut(s![..2, 1, ..2]).fill(2);
+        state.set_drop_rate(1, 1, 2);
+        state
+    }
+
+    let mut a = f();
+    for _ in 0..10 {
+        a.random_tick();
+    }
+    let seed = a.export_rng();
+
+    let mut b = f();
+    b.data.assign(&a.data);
+    b.import_rng(seed);
+    assert_eq!(a.rng, b.rng);
+    for _ in 0..50 {
+        a.random_tick();
+        b.random_tick();
+        for s in [&mut a, &mut b] {
+            s.drones[0].command = Command::BreakBlock(Dir::Up);
+            execute_commands(s);
+        }
+        assert_eq!(hash::state_hash(&a), hash::state_hash(&b));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rng_export_untouched() -> Result<(), Error> {
+    let mut a = State::new(SEED, [16, 4, 16], 16, 1, 64);
+    a.data.slice_mut(s![.., ..2, ..]).fill(1);
+    let mut b = State::new(SEED, [16, 4, 16], 16, 1, 64);
+    b.data.assign(&a.data);
+
+    // Exporting every tick doesn't change the simulation
+    for _ in 0..50 {
+        a.export_rng();
+        a.random_tick();
+        b.random_tick();
+        assert_eq!(hash::state_hash(&a), hash::state_hash(&b));
+    }
+    assert_eq!(a.rng, b.rng);
+
+    Ok(())
+}
+
+#[test]
+fn test_dir_offset() -> Result<(), Error> {
+    let size = (3, 3, 3);
+    for (dir, off) in [
+        (Dir::Noop, (0, 0, 0)),
+        (Dir::Up, (0, 1, 0)),
+        (Dir::Down, (0, -1, 0)),
+        (Dir::Left, (1, 0, 0)),
+        (Dir::Right, (-1, 0, 0)),
+        (Dir::Back, (0, 0, 1)),
+        (Dir::Front, (0, 0, -1)),
+    ] {
+        assert_eq!(dir.offset(), off);
+        let (x, y, z) = off;
+        assert_eq!(x.abs() + y.abs() + z.abs(), (dir != Dir::Noop) as isize);
+        assert_eq!(
+            dir.move_coord(&size, (1, 1, 1)),
+            Some(((1 + x) as _, (1 + y) as _, (1 + z) as _))
+        );
+        assert_eq!(
+            dir.move_coord(&size, ((1 + x) as _, (1 + y) as _, (1 + z) as _)),
+            if dir == Dir::Noop {
+                Some((1, 1, 1))
+            } else {
+                None
+            }
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_event_block_broken() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 1, 1);
+    state.set_drop_rate(1, 1, 1);
+
+    state.data[(1, 0, 0)] = 1;
+    state.drones[0].command = Command::BreakBlock(Dir::Left);
+    execute_commands(&mut state);
+
+    assert_eq!(
+        state.events,
+        [
+            Event::ItemPickedUp {
+                drone: 0,
+                item: 1,
+                count: 1
+            },
+            Event::BlockBroken {
+                x: 1,
+                y: 0,
+                z: 0,
+                block: 1
+            },
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_stack_size_override() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 1, 1);
+    state.set_drop_rate(1, 1, 1);
+    state.set_stack_size(1, 4);
+
+    for _ in 0..6 {
+        state.data[(1, 0, 0)] = 1;
+        state.drones[0].command = Command::BreakBlock(Dir::Left);
+        execute_commands(&mut state);
+    }
+
+    let inv = state.drones[0].inventory();
+    assert_eq!(inv[0].item_id, NonZeroU16::new(1));
+    assert_eq!(inv[0].count, 4);
+    assert_eq!(inv[1].item_id, NonZeroU16::new(1));
+    assert_eq!(inv[1].count, 2);
+
+    // Restack respects the override
+    state.drones[0].command = Command::Restack;
+    execute_commands(&mut state);
+    let inv = state.drones[0].inventory();
+    assert_eq!((inv[0].count, inv[1].count), (4, 2));
+
+    // Single put caps at the override too
+    let mut inv = [Inventory::default(); 2];
+    let mut src = Inventory::new(NonZeroU16::new(1), 7);
+    Inventory::try_put_one_with(&mut inv, &mut src, |_| 4);
+    assert_eq!((inv[0].count, inv[1].count, src.count), (4, 3, 0));
+
+    Ok(())
+}
+
+#[test]
+fn test_recv_item_stack_size() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 2, 1);
+    state.drones[1].x = 1;
+    update_all_drones(&mut state);
+    state.set_stack_size(1, 16);
+    state.drones[1].inventory[0] = Inventory::new(NonZeroU16::new(1), 64);
+
+    state.drones[0].command = Command::RecvItem(Dir::Left, 0);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].inventory[0].count, 16);
+    assert_eq!(state.drones[1].inventory[0].count, 48);
+
+    Ok(())
+}
+
+#[test]
+fn test_paint() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 1, 1);
+
+    state.data[(1, 0, 0)] = 1;
+    state.update_mesh(usize::MAX);
+    state.drones[0].command = Command::Paint(Dir::Left, 5);
+    execute_commands(&mut state);
+
+    assert_eq!(state.drones[0].result, CommandResult::Ok);
+    assert_eq!(state.data[(1, 0, 0)] & 0xff, 1);
+    assert_eq!(blocks::block_meta(state.data[(1, 0, 0)]), 5);
+    assert!(state.mesh[(0, 0, 0)].dirty);
+
+    state.update_mesh(usize::MAX);
+    let m = &state.mesh[(0, 0, 0)];
+    assert_eq!(m.color.len(), m.vertex.len());
+    assert!(m.color.iter().all(|&c| c != Vec4::ONE));
+
+    // Air cannot be painted
+    state.data[(1, 0, 0)] = 0;
+    state.drones[0].command = Command::Paint(Dir::Left, 5);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::NoTarget);
+
+    Ok(())
+}
+
+#[test]
+fn test_export_inventory() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 2, 1);
+    state.drones[1].inventory[0] = Inventory::new(NonZeroU16::new(1), 3);
+    state.drones[1].inventory[2] = Inventory::new(NonZeroU16::new(2), 5).with_data_hash(7);
+
+    let inv = state.export_inventory(1).unwrap().to_vec();
+    let expected = state.drones[1].inventory();
+    assert_eq!(inv.len(), expected.len());
+    for (a, b) in inv.iter().zip(expected) {
+        assert_eq!((a.key(), a.count), (b.key(), b.count));
+    }
+
+    assert!(state.export_inventory(2).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_place_block_ray() -> Result<(), Error> {
+    let mut state = State::new(SEED, [8, 1, 1], 16, 1, 1);
+    state.drones[0].x = 3;
+    update_all_drones(&mut state);
+    state.data[(6, 0, 0)] = 1;
+
+    // Place against wall from a distance
+    let c = state.place_block_ray(Vec3::new(0.2, 0.3, 0.7), Vec3::new(1., 0.1, 0.), 2);
+    assert_eq!(c, Some((5, 0, 0)));
+    assert_eq!(state.data[(5, 0, 0)], 2);
+
+    // Next ray hits new block, but drone is in the way
+    state.data[(4, 0, 0)] = 1;
+    let c = state.place_block_ray(Vec3::new(0.5, 0.5, 0.5), Vec3::new(1., 0., 0.), 2);
+    assert_eq!(c, None);
+    assert_eq!(state.data[(3, 0, 0)] & 0xff, 0);
+
+    // Miss
+    let c = state.place_block_ray(Vec3::new(0.5, 0.5, 0.5), Vec3::new(-1., 0., 0.), 2);
+    assert_eq!(c, None);
+
+    // Air and unregistered blocks are rejected
+    state.data[(4, 0, 0)] = 0;
+    state.events.clear();
+    for b in [0, 255] {
+        let c = state.place_block_ray(Vec3::new(3.5, 0.5, 0.5), Vec3::new(1., 0., 0.), b);
+        assert_eq!(c, None);
+        assert!(state.events.is_empty());
+    }
+    assert_eq!(state.data[(4, 0, 0)], 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_look() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 2, 1], 16, 1, 1);
+    assert_eq!(state.drones[0].facing, Dir::Front);
+
+    state.drones[0].command = Command::Look(Dir::Up);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].facing, Dir::Up);
+    assert_eq!((state.drones[0].x, state.drones[0].y), (0, 0));
+
+    // Moving turns toward horizontal movement only
+    state.drones[0].command = Command::Move(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].facing, Dir::Left);
+    state.drones[0].command = Command::Move(Dir::Up);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].facing, Dir::Left);
+
+    state.drones[0].command = Command::Look(Dir::Noop);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::NoTarget);
+    assert_eq!(state.drones[0].facing, Dir::Left);
+
+    Ok(())
+}
+
+#[test]
+fn test_drop_policy() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 1, 1);
+    state.set_drop_rate(1, 1, 1);
+
+    let mut run = |policy| {
+        let d = &mut state.drones[0];
+        (d.drop_policy, d.drop_cursor) = (policy, 0);
+        d.inventory = [Inventory::default(); INVENTORY_SIZE];
+        d.inventory[0] = Inventory::new(NonZeroU16::new(2), 1);
+        d.inventory[2] = Inventory::new(NonZeroU16::new(1), 1);
+        for _ in 0..2 {
+            state.data[(1, 0, 0)] = 1;
+            state.drones[0].command = Command::BreakBlock(Dir::Left);
+            execute_commands(&mut state);
+        }
+        state.drones[0].inventory.map(|v| v.count)
+    };
+
+    // Existing stack in slot 2
+    assert_eq!(run(DropPolicy::Stack)[..4], [1, 0, 3, 0]);
+    // Empty slot 1 comes first
+    assert_eq!(run(DropPolicy::Ordered)[..4], [1, 2, 1, 0]);
+    // Slot 1, then next one after it
+    assert_eq!(run(DropPolicy::RoundRobin)[..4], [1, 1, 2, 0]);
+
+    Ok(())
+}
+
+#[test]
+fn test_mesh_split() -> Result<(), Error> {
+    let mut state = State::new(SEED, [4, 1, 1], 16, 1, 1);
+    state.mesh_split = true;
+    state.data[(1, 0, 0)] = 2;
+    state.data[(2, 0, 0)] = 1;
+    state.data[(3, 0, 0)] = 2;
+    state.mesh[(0, 0, 0)].dirty = true;
+    state.update_mesh(usize::MAX);
+
+    let m = &state.mesh[(0, 0, 0)];
+    assert_eq!(m.ranges.len(), 2);
+    let (a, b) = (m.ranges[0], m.ranges[1]);
+    assert_eq!((a.block, b.block), (1, 2));
+    assert_eq!((a.start, a.end, b.start), (0, b.start, a.end));
+    assert_eq!(b.end as usize, m.index.len());
+
+    Ok(())
+}
+
+#[test]
+fn test_reverse_winding() -> Result<(), Error> {
+    let mut state = State::new(SEED, [4, 2, 1], 16, 1, 1);
+    state.data[(1, 0, 0)] = 1;
+    state.data[(2, 0, 0)] = 2;
+    state.mesh[(0, 0, 0)].dirty = true;
+    state.update_mesh(usize::MAX);
+    let (vertex, index) = {
+        let m = &state.mesh[(0, 0, 0)];
+        (m.vertex.clone(), m.index.clone())
+    };
+    assert!(!index.is_empty());
+
+    state.mesh_reverse_winding = true;
+    state.mesh[(0, 0, 0)].dirty = true;
+    state.update_mesh(usize::MAX);
+    let m = &state.mesh[(0, 0, 0)];
+    assert_eq!(m.vertex, vertex);
+    assert_eq!(m.index.len(), index.len());
+    for (a, b) in m.index.chunks_exact(3).zip(index.chunks_exact(3)) {
+        assert_eq!(a, [b[0], b[2], b[1]]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_wait() -> Result<(), Error> {
+    let mut state = State::new(SEED, [8, 1, 1], 16, 1, 1);
+
+    state.drones[0].command = Command::Wait(3);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::Ok);
+
+    for _ in 0..2 {
+        state.drones[0].command = Command::Move(Dir::Left);
+        execute_commands(&mut state);
+        assert_eq!(state.drones[0].result, CommandResult::Waiting);
+        assert_eq!(state.drones[0].x, 0);
+    }
+
+    state.drones[0].command = Command::Move(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::Ok);
+    assert_eq!(state.drones[0].x, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_clear_all_commands() -> Result<(), Error> {
+    let mut state = State::new(SEED, [4, 1, 1], 16, 4, 1);
+    for (i, d) in state.drones.iter_mut().enumerate() {
+        d.x = i;
+    }
+    update_all_drones(&mut state);
+
+    state.drones[0].command = Command::Wait(5);
+    execute_commands(&mut state);
+    state.drones[1].command = Command::Move(Dir::Up);
+    state.drones[2].command = Command::BreakBlock(Dir::Down);
+    assert_eq!(state.pending_command_count(), 3);
+
+    state.clear_all_commands();
+    assert_eq!(state.pending_command_count(), 0);
+    assert!(state
+        .drones
+        .iter()
+        .all(|d| matches!(d.command, Command::Noop) && d.wait == 0));
+
+    Ok(())
+}
+
+#[test]
+fn test_tickable_after_place() -> Result<(), Error> {
+    let mut state = State::new(SEED, [8, 2, 4], 4, 1, 64);
+    state.tick_order = TickOrder::EnvironmentFirst;
+    state.drones[0].x = 4;
+    update_all_drones(&mut state);
+    state.update_mesh(usize::MAX);
+    state.drones[0].inventory[0] = Inventory::new(NonZeroU16::new(1), 1);
+
+    // Dirt placed into all-air chunk (0, 0, 0)
+    state.drones[0].command = Command::PlaceBlock(Dir::Right, 0);
+    state.tick();
+    state.update_mesh(usize::MAX);
+    assert_eq!(state.drones[0].result, CommandResult::Ok);
+    assert_eq!(state.data[(3, 0, 0)] & 0xff, 1);
+    assert!(state.tickable[(0, 0, 0)]);
+
+    state.tick();
+    assert_eq!(state.tick_chunks, [(0, 0, 0)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_frozen_chunk() -> Result<(), Error> {
+    let mut state = State::new(SEED, [8, 2, 4], 4, 1, 64);
+    state.frozen[(1, 0, 0)] = true;
+    state.drones[0].x = 3;
+    update_all_drones(&mut state);
+    state.drones[0].inventory[0] = Inventory::new(NonZeroU16::new(1), 1);
+
+    // Cannot edit across chunk boundary into frozen chunk
+    state.drones[0].command = Command::PlaceBlock(Dir::Left, 0);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::Blocked);
+    assert_eq!(state.data[(4, 0, 0)], 0);
+    assert_eq!(state.drones[0].inventory[0].count, 1);
+
+    state.data[(4, 0, 0)] = 1;
+    state.drones[0].command = Command::BreakBlock(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::Blocked);
+    assert_eq!(state.data[(4, 0, 0)], 1);
+
+    // Frozen crops do not grow
+    state.data[(5, 0, 1)] = blocks::CROP as _;
+    state.data[(1, 0, 1)] = blocks::CROP as _;
+    state.set_growth_ticks(blocks::CROP, 4);
+    for m in &mut state.mesh {
+        m.dirty = true;
+    }
+    for _ in 0..8 {
+        state.random_tick();
+    }
+    assert!(!state.tick_chunks.contains(&(1, 0, 0)));
+    assert_eq!(state.data[(5, 0, 1)], blocks::CROP as u32);
+    assert!(blocks::is_mature_crop(state.data[(1, 0, 1)]));
+
+    Ok(())
+}
+
+#[test]
+fn test_transfer_all() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 2, 1);
+    state.drones[1].x = 1;
+    update_all_drones(&mut state);
+
+    state.drones[1].inventory_size = 2;
+    state.drones[1].inventory[0] = Inventory::new(NonZeroU16::new(1), 60);
+    for (i, v) in state.drones[0].inventory[..3].iter_mut().enumerate() {
+        *v = Inventory::new(NonZeroU16::new(i as u16 + 1), 10);
+    }
+
+    state.drones[0].command = Command::TransferAll(Dir::Left);
+    execute_commands(&mut state);
+
+    // Item 1 tops up full stack and spills into last slot, others do not fit
+    let inv = state.drones[1].inventory();
+    assert_eq!(
+        (inv[0].count, inv[1].item_id, inv[1].count),
+        (64, NonZeroU16::new(1), 6)
+    );
+    let inv = state.drones[0].inventory();
+    assert!(inv[0].item_id.is_none());
+    assert_eq!((inv[1].item_id, inv[1].count), (NonZeroU16::new(2), 10));
+    assert_eq!((inv[2].item_id, inv[2].count), (NonZeroU16::new(3), 10));
+
+    Ok(())
+}
+
+#[test]
+fn test_tick_order() -> Result<(), Error> {
+    fn f(order: TickOrder) -> CommandResult {
+        let mut state = State::new(SEED, [2, 1, 1], 16, 1, 1);
+        state.tick_order = order;
+        state.set_growth_ticks(blocks::CROP, 1);
+        // Matures on next growth tick
+        state.data[(1, 0, 0)] = blocks::CROP as _;
+        state.mesh[(0, 0, 0)].dirty = true;
+
+        state.drones[0].command = Command::Harvest(Dir::Left);
+        state.tick();
+        state.drones[0].result
+    }
+
+    assert_eq!(f(TickOrder::CommandFirst), CommandResult::NoTarget);
+    assert_eq!(f(TickOrder::EnvironmentFirst), CommandResult::Ok);
+    // Growth happens after commands when interleaved
+    assert_eq!(f(TickOrder::Interleave), CommandResult::NoTarget);
+
+    Ok(())
+}
+
+#[test]
+fn test_paused() -> Result<(), Error> {
+    let mut state = State::new(SEED, [4, 1, 1], 16, 1, 1);
+    state.paused = true;
+
+    state.drones[0].command = Command::Move(Dir::Left);
+    state.tick();
+    assert_eq!(state.drones[0].x, 0);
+    assert_eq!(state.elapsed_ticks, 0);
+
+    state.step_once();
+    assert_eq!(state.drones[0].x, 1);
+    assert_eq!(state.elapsed_ticks, 1);
+
+    state.paused = false;
+    state.drones[0].command = Command::Move(Dir::Left);
+    state.tick();
+    assert_eq!(state.drones[0].x, 2);
+    assert_eq!(state.elapsed_ticks, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_is_region_air() -> Result<(), Error> {
+    let mut state = State::new(SEED, [32, 4, 32], 16, 1, 1);
+    // Drone cell is only flagged as occupied
+    assert!(state.is_region_air([0; 3], [32, 4, 32]));
+    assert!(state.is_region_air([4, 0, 4], [4, 4, 8]));
+
+    state.data[(20, 1, 3)] = 1;
+    assert!(!state.is_region_air([0; 3], [32, 4, 32]));
+    assert!(state.is_region_air([0; 3], [20, 4, 32]));
+    // Spans chunk boundary at x = 16
+    assert!(!state.is_region_air([10, 0, 0], [21, 2, 4]));
+    assert!(state.is_region_air([10, 2, 0], [21, 4, 4]));
+    // Clipped to level
+    assert!(!state.is_region_air([20, 0, 0], [100, 100, 100]));
+
+    Ok(())
+}
+
+#[test]
+fn test_face_list() -> Result<(), Error> {
+    let mut state = State::new(SEED, [3, 3, 3], 16, 1, 1);
+    state.data[(1, 1, 1)] = 1;
+
+    let faces = state.export_face_list([0, 0, 0]).unwrap();
+    assert_eq!(faces.len(), 6);
+    assert!(faces
+        .iter()
+        .all(|f| (f.x, f.y, f.z, f.block) == (1, 1, 1, 1)));
+    assert_eq!(faces.iter().map(|f| f.dir).unique().count(), 6);
+
+    // Covered face is hidden, world edge face is hidden without boundary
+    state.data[(1, 0, 1)] = 1;
+    state.mesh_boundary = false;
+    let faces = state.export_face_list([0, 0, 0]).unwrap();
+    assert_eq!(faces.len(), 5 + 4);
+    assert!(!faces.iter().any(|f| f.dir == Dir::Down));
+    assert!(!faces.iter().any(|f| (f.y, f.dir) == (0, Dir::Up)));
+
+    assert!(state.export_face_list([1, 0, 0]).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_placement_support() -> Result<(), Error> {
+    assert_eq!(blocks::placement_support(blocks::CROP), Some(Dir::Down));
+    assert_eq!(blocks::placement_support(1), None);
+
+    let mut state = State::new(SEED, [2, 2, 1], 16, 1, 1);
+    state.drones[0].inventory[0] = Inventory::new(NonZeroU16::new(blocks::CROP_SEED), 2);
+
+    // Floating
+    state.drones[0].y = 1;
+    update_all_drones(&mut state);
+    state.drones[0].command = Command::PlaceBlock(Dir::Left, 0);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::Blocked);
+    assert_eq!(state.data[(1, 1, 0)], 0);
+    assert!(!blocks::is_supported(blocks::CROP, (1, 1, 0), &state.data));
+
+    // On dirt
+    state.data[(1, 0, 0)] = 1;
+    assert!(blocks::is_supported(blocks::CROP, (1, 1, 0), &state.data));
+    state.drones[0].command = Command::PlaceBlock(Dir::Left, 0);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].result, CommandResult::Ok);
+    assert_eq!(state.data[(1, 1, 0)], blocks::CROP as u32);
+
+    Ok(())
+}
+
+#[test]
+fn test_transfer_rate() -> Result<(), Error> {
+    let mut state = State::new(SEED, [2, 1, 1], 16, 2, 1);
+    state.drones[1].x = 1;
+    update_all_drones(&mut state);
+    state.drones[0].transfer_rate = 1;
+    state.drones[0].inventory[0] = Inventory::new(NonZeroU16::new(1), 3);
+
+    for i in 1..=3 {
+        state.drones[0].command = Command::SendItem(Dir::Left, 0);
+        execute_commands(&mut state);
+        assert_eq!(state.drones[1].inventory[0].count, i);
+        assert_eq!(state.drones[0].inventory[0].count, 3 - i);
+    }
+    assert!(state.drones[0].inventory[0].item_id.is_none());
+
+    // Pulling and dumping are limited too
+    state.drones[0].transfer_rate = 2;
+    state.drones[0].command = Command::RecvItem(Dir::Left, 0);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].inventory[0].count, 2);
+    state.drones[0].command = Command::TransferAll(Dir::Left);
+    execute_commands(&mut state);
+    assert_eq!(state.drones[0].inventory[0].count, 0);
+    assert_eq!(state.drones[1].inventory[0].count, 3);
+
+    Ok(())
+}
@@ -37,7 +37,7 @@ drone! {
             }
         }
 
-        let Some((i, _)) = ctx.drone.inventory.iter().enumerate().find(|&(_, v)| v.item_id == NonZeroU16::new(1)) else {
+        let Some((i, _)) = ctx.drone.inventory().iter().enumerate().find(|&(_, v)| v.item_id == NonZeroU16::new(1)) else {
             print_log(format_args!("Has no item in inventory"));
             return;
         };